@@ -0,0 +1,91 @@
+//! Polls the LIS2DH12 over [`SharedI2c`] at a fixed rate, pushing converted
+//! g-values (plus temperature) onto the accelerometer [`PubSubChannel`] that
+//! the websocket, BLE and MQTT bridges all subscribe to, and gestures onto the
+//! parallel gesture channel. The full-scale range can be changed at runtime
+//! via `Command::SetFullScale`, applied on the next poll.
+
+use bhbadge2024::{
+    lis2dh12::{F32x3, FullScale, Lis2dh12},
+    shared_i2c::SharedI2c,
+};
+use embassy_executor::Spawner;
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    pubsub::Publisher,
+    signal::Signal,
+};
+use embassy_time::Timer;
+use feature_creep_types::Gesture;
+
+use crate::webserver::{ACCEL_CHANNEL_SUBS, WEB_TASK_POOL_SIZE};
+
+static RANGE: Signal<CriticalSectionRawMutex, FullScale> = Signal::new();
+
+/// Changes the accelerometer's full-scale range. Called from the websocket
+/// handler (and the MQTT bridge) on `Command::SetFullScale`, applied by the
+/// polling task on its next tick.
+pub fn set_range(range: feature_creep_types::FullScale) {
+    RANGE.signal(match range {
+        feature_creep_types::FullScale::G2 => FullScale::G2,
+        feature_creep_types::FullScale::G4 => FullScale::G4,
+        feature_creep_types::FullScale::G8 => FullScale::G8,
+        feature_creep_types::FullScale::G16 => FullScale::G16,
+    });
+}
+
+pub async fn init(
+    spawner: &Spawner,
+    shared_i2c: SharedI2c,
+    publisher: Publisher<'static, NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1>,
+    gesture_publisher: Publisher<'static, NoopRawMutex, Gesture, 1, WEB_TASK_POOL_SIZE, 1>,
+) {
+    let mut lis2dh12 = Lis2dh12::new(shared_i2c, bhbadge2024::lis2dh12::SlaveAddr::Alternative(true))
+        .await
+        .unwrap();
+
+    lis2dh12.reset().await.unwrap();
+    lis2dh12
+        .set_odr(bhbadge2024::lis2dh12::Odr::Hz400)
+        .await
+        .unwrap();
+    lis2dh12
+        .set_mode(bhbadge2024::lis2dh12::Mode::Normal)
+        .await
+        .unwrap();
+    lis2dh12.set_fs(FullScale::G16).await.unwrap();
+    lis2dh12.enable_axis((true, true, true)).await.unwrap();
+    lis2dh12.enable_temp(true).await.unwrap();
+    lis2dh12.configure_gestures().await.unwrap();
+
+    spawner.must_spawn(read_accelerometer(lis2dh12, publisher, gesture_publisher));
+}
+
+#[embassy_executor::task]
+async fn read_accelerometer(
+    mut lis2dh12: Lis2dh12,
+    publisher: Publisher<'static, NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1>,
+    gesture_publisher: Publisher<'static, NoopRawMutex, Gesture, 1, WEB_TASK_POOL_SIZE, 1>,
+) {
+    loop {
+        if let Some(range) = RANGE.try_take() {
+            lis2dh12.set_fs(range).await.unwrap();
+        }
+
+        let dir = lis2dh12.accel_norm().await.unwrap();
+        let temperature = lis2dh12.get_temp_outf().await.unwrap();
+        publisher.publish_immediate((dir, temperature + 20.0));
+
+        if let Some(gesture) = lis2dh12.poll_gesture().await.unwrap() {
+            if matches!(gesture, bhbadge2024::lis2dh12::Gesture::SingleTap) {
+                crate::animation::cycle_animation();
+            }
+            gesture_publisher.publish_immediate(match gesture {
+                bhbadge2024::lis2dh12::Gesture::SingleTap => Gesture::SingleTap,
+                bhbadge2024::lis2dh12::Gesture::DoubleTap => Gesture::DoubleTap,
+                bhbadge2024::lis2dh12::Gesture::FreeFall => Gesture::FreeFall,
+            });
+        }
+
+        Timer::after_millis(500).await;
+    }
+}
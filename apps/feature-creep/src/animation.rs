@@ -0,0 +1,118 @@
+//! Plays the built-in animations (solid, rainbow cycle, breathing, comet) on top of
+//! [`Ws2812b`], selected over the websocket via `Command::SetAnimation`. The task owns
+//! the frame buffer updates on its own timer, but backs off for a few seconds whenever
+//! an explicit `ChangeColor` comes in so it doesn't immediately paint over a manual pick.
+
+use bhbadge2024::ws2812b::Ws2812b;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+use feature_creep_types::Animation;
+
+const TICK: Duration = Duration::from_millis(33);
+const MANUAL_OVERRIDE_HOLDOFF: Duration = Duration::from_secs(3);
+
+/// Fixed rotation cycled through by [`cycle_animation`].
+const CYCLE: [Animation; 4] = [
+    Animation::Solid { rgb: (255, 255, 255) },
+    Animation::RainbowCycle,
+    Animation::Breathing { rgb: (0, 120, 255) },
+    Animation::Comet { rgb: (255, 80, 0) },
+];
+
+static SELECTED: Signal<CriticalSectionRawMutex, Animation> = Signal::new();
+static MANUAL_UNTIL: Signal<CriticalSectionRawMutex, Instant> = Signal::new();
+static CYCLE_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+/// Switches the running animation. Called from the websocket handler on `SetAnimation`.
+pub fn select_animation(animation: Animation) {
+    SELECTED.signal(animation);
+}
+
+/// Advances to the next built-in animation in a fixed rotation. Called when a tap
+/// gesture is detected on the accelerometer.
+pub fn cycle_animation() {
+    let next = (CYCLE_INDEX.fetch_add(1, Ordering::Relaxed) + 1) % CYCLE.len();
+    SELECTED.signal(CYCLE[next]);
+}
+
+/// Tells the animation task to back off for a few seconds. Called from the websocket
+/// handler whenever it applies an explicit `ChangeColor`.
+pub fn notify_manual_override() {
+    MANUAL_UNTIL.signal(Instant::now() + MANUAL_OVERRIDE_HOLDOFF);
+}
+
+pub fn init(spawner: &Spawner, ws2812b: Ws2812b) {
+    spawner.must_spawn(animate(ws2812b));
+}
+
+#[embassy_executor::task]
+async fn animate(ws2812b: Ws2812b) {
+    // No animation selected yet: leave the frame buffer alone rather than painting
+    // over whatever `ws2812b.restore_from_flash()` put there at boot, or over a
+    // manual `ChangeColor` once its holdoff expires.
+    let mut animation: Option<Animation> = None;
+    let mut manual_until: Option<Instant> = None;
+    let mut tick: u32 = 0;
+
+    loop {
+        match select(select(SELECTED.wait(), MANUAL_UNTIL.wait()), Timer::after(TICK)).await {
+            Either::First(Either::First(next)) => animation = Some(next),
+            Either::First(Either::Second(until)) => manual_until = Some(until),
+            Either::Second(()) => {
+                let overridden = manual_until.is_some_and(|until| Instant::now() < until);
+                if !overridden {
+                    if let Some(animation) = animation {
+                        step(&ws2812b, animation, tick);
+                        tick = tick.wrapping_add(1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn step(ws2812b: &Ws2812b, animation: Animation, tick: u32) {
+    match animation {
+        Animation::Solid { rgb } => ws2812b.with_frame_buffer(|frame| {
+            for index in 0..16 {
+                frame.set_pixel(index, rgb);
+            }
+        }),
+        Animation::RainbowCycle => ws2812b.with_frame_buffer(|frame| {
+            for index in 0..16 {
+                let hue = (tick as f32 * 2.0 + index as f32 * (360.0 / 16.0)) % 360.0;
+                frame.set_pixel_hsv(index, hue, 1.0, 1.0);
+            }
+        }),
+        Animation::Breathing { rgb } => {
+            let value = (tick as f32 * 0.05).sin() * 0.5 + 0.5;
+            ws2812b.with_frame_buffer(|frame| {
+                for index in 0..16 {
+                    frame.set_pixel(index, scale(rgb, value));
+                }
+            });
+        }
+        Animation::Comet { rgb } => {
+            let head = tick as usize % 16;
+            ws2812b.with_frame_buffer(|frame| {
+                for index in 0..16 {
+                    let distance = (head + 16 - index) % 16;
+                    let fade = 1.0 - (distance as f32 / 4.0).min(1.0);
+                    frame.set_pixel(index, scale(rgb, fade));
+                }
+            });
+        }
+    }
+}
+
+fn scale(rgb: (u8, u8, u8), factor: f32) -> (u8, u8, u8) {
+    let (r, g, b) = rgb;
+    (
+        (r as f32 * factor) as u8,
+        (g as f32 * factor) as u8,
+        (b as f32 * factor) as u8,
+    )
+}
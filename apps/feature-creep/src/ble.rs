@@ -0,0 +1,176 @@
+//! BLE GATT control path, parallel to the websocket in [`crate::webserver`].
+//!
+//! This lets a phone drive the LEDs and read the accelerometer without joining
+//! the `bornhack` Wi-Fi network: a single custom service exposes a writable
+//! "LED" characteristic that accepts the same [`Command`] payloads the
+//! websocket parses (acting on `ChangeColor`, `SetBrightness` and
+//! `QueryColors`; other commands are logged and ignored), and a notify
+//! characteristic that streams
+//! [`Message::Accelerometer`] off the same [`PubSubChannel`] the websocket
+//! subscribes to.
+
+use bhbadge2024::{lis2dh12::F32x3, ws2812b::Ws2812b};
+use embassy_executor::Spawner;
+use embassy_futures::select::Either;
+use embassy_sync::{
+    blocking_mutex::raw::NoopRawMutex,
+    pubsub::{Subscriber, WaitResult},
+};
+use esp_println::println;
+use esp_wifi::{ble::controller::BleConnector, EspWifiInitialization};
+use feature_creep_types::{Command, Message};
+use trouble_host::{
+    attribute::{AttributeTable, Characteristic, CharacteristicProp, Service, Uuid},
+    gatt::GattEvent,
+    prelude::*,
+};
+
+use crate::webserver::{AppState, ACCEL_CHANNEL_SUBS};
+
+// Randomly generated, badge-specific 128-bit UUIDs.
+const SERVICE_UUID: Uuid = Uuid::new_long([
+    0x7a, 0x1e, 0x6f, 0x10, 0x41, 0x0a, 0x43, 0x1b, 0x9e, 0x2d, 0x2e, 0x4f, 0x0c, 0x1a, 0x9b, 0x56,
+]);
+const LED_CHARACTERISTIC_UUID: Uuid = Uuid::new_long([
+    0x7a, 0x1e, 0x6f, 0x11, 0x41, 0x0a, 0x43, 0x1b, 0x9e, 0x2d, 0x2e, 0x4f, 0x0c, 0x1a, 0x9b, 0x56,
+]);
+const ACCEL_CHARACTERISTIC_UUID: Uuid = Uuid::new_long([
+    0x7a, 0x1e, 0x6f, 0x12, 0x41, 0x0a, 0x43, 0x1b, 0x9e, 0x2d, 0x2e, 0x4f, 0x0c, 0x1a, 0x9b, 0x56,
+]);
+
+const MAX_CONNECTIONS: usize = 1;
+const L2CAP_MTU: usize = 251;
+
+/// Spawns the BLE peripheral task, sharing the radio `init` handle already
+/// brought up by [`crate::net::init`]'s Wi-Fi backend. Safe to run alongside
+/// [`crate::webserver::init`]: both just read/write `ws2812b` and subscribe to
+/// the same accelerometer channel.
+pub async fn init(
+    spawner: &Spawner,
+    wifi_init: &'static EspWifiInitialization,
+    bluetooth: esp_hal::peripherals::BT,
+    app_state: &'static AppState,
+) {
+    let connector = BleConnector::new(wifi_init, bluetooth);
+    spawner.must_spawn(ble_task(connector, app_state));
+}
+
+#[embassy_executor::task]
+async fn ble_task(controller: BleConnector<'static>, app_state: &'static AppState) {
+    let mut resources: HostResources<NoopRawMutex, MAX_CONNECTIONS, L2CAP_MTU> =
+        HostResources::new();
+    let stack = trouble_host::new(controller, &mut resources);
+    let Host {
+        mut peripheral,
+        runner,
+        ..
+    } = stack.build();
+
+    let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+    let service = Service::new(SERVICE_UUID);
+    // Notify is needed alongside Write so the QueryColors reply (sent back as a
+    // notification on this same characteristic) can actually reach the client.
+    let led_characteristic = Characteristic::new(
+        LED_CHARACTERISTIC_UUID,
+        &[CharacteristicProp::Write, CharacteristicProp::Notify],
+        &mut [0u8; 32],
+    );
+    let accel_characteristic = Characteristic::new(
+        ACCEL_CHARACTERISTIC_UUID,
+        &[CharacteristicProp::Notify],
+        &mut [0u8; 16],
+    );
+    table.add_service(service);
+    let led_handle = table.add_characteristic(led_characteristic);
+    let accel_handle = table.add_characteristic(accel_characteristic);
+
+    let mut subscriber: Subscriber<'static, NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1> =
+        app_state.channel.subscriber().unwrap();
+
+    let advertise = async {
+        loop {
+            println!("BLE: advertising as badge2024");
+            let advertiser = peripheral
+                .advertise(
+                    &Default::default(),
+                    Advertisement::ConnectableScannableUndirected {
+                        adv_data: &[],
+                        scan_data: b"badge2024",
+                    },
+                )
+                .await
+                .unwrap();
+            let conn = advertiser.accept().await.unwrap();
+            println!("BLE: connected");
+            gatt_session(
+                &conn,
+                &mut table,
+                led_handle,
+                accel_handle,
+                app_state.ws2812b,
+                &mut subscriber,
+            )
+            .await;
+        }
+    };
+
+    embassy_futures::join::join(runner.run(), advertise).await;
+}
+
+async fn gatt_session(
+    conn: &Connection<'_>,
+    table: &mut AttributeTable<'_, NoopRawMutex, 10>,
+    led_handle: u16,
+    accel_handle: u16,
+    ws2812b: Ws2812b,
+    subscriber: &mut Subscriber<'static, NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1>,
+) {
+    // Large enough for a full `Message::CurrentColors` reply (up to 231 bytes of JSON).
+    let mut buffer = [0u8; 256];
+
+    loop {
+        let event = embassy_futures::select::select(conn.next(), subscriber.next_message()).await;
+        match event {
+            Either::First(GattEvent::Write { handle, data }) if handle == led_handle => {
+                match serde_json_core::from_slice(data) {
+                    Ok((Command::ChangeColor { index, rgb }, _)) if (index as usize) < 16 => {
+                        crate::animation::notify_manual_override();
+                        ws2812b.set_pixel(index as usize, rgb);
+                    }
+                    Ok((Command::SetBrightness(brightness), _)) => {
+                        ws2812b.set_brightness(brightness);
+                    }
+                    Ok((Command::QueryColors, _)) => {
+                        let mut res = [(0u8, 0u8, 0u8); 16];
+                        ws2812b.with_frame_buffer(|f| {
+                            for (i, pix) in f.raw_mut().iter().enumerate() {
+                                res[i] = (pix[1], pix[0], pix[2]);
+                            }
+                        });
+                        if let Ok(len) =
+                            serde_json_core::to_slice(&Message::CurrentColors(res), &mut buffer)
+                        {
+                            table.notify(led_handle, conn, &buffer[..len]).await.ok();
+                        }
+                    }
+                    Ok((command, _)) => println!("BLE: unexpected command: {command:?}"),
+                    Err(e) => println!("BLE: could not parse command: {e:?}"),
+                }
+            }
+            Either::First(GattEvent::Disconnected) => break,
+            Either::First(_) => {}
+            Either::Second(WaitResult::Lagged(_)) => {}
+            Either::Second(WaitResult::Message((dir, temperature))) => {
+                if let Ok(len) = serde_json_core::to_slice(
+                    &Message::Accelerometer([dir.x, dir.y, dir.z, temperature]),
+                    &mut buffer,
+                ) {
+                    table
+                        .notify(accel_handle, conn, &buffer[..len])
+                        .await
+                        .ok();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Minimal single-lease DHCP server, spawned alongside the AP in
+//! [`crate::provisioning`] so an attendee's phone gets an IP automatically
+//! instead of needing a hand-configured static address in `192.168.71.0/24`
+//! just to reach the setup page.
+//!
+//! Implements only the two request/reply pairs needed to hand out one lease -
+//! DISCOVER/OFFER and REQUEST/ACK, both for the single fixed address below.
+//! There's no lease table and no renewal tracking: a second client requesting
+//! a lease is simply offered the same address. Good enough for one phone to
+//! join briefly and submit Wi-Fi credentials, not a general-purpose server.
+
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    IpAddress, IpEndpoint, Ipv4Address,
+};
+use esp_println::println;
+
+use crate::provisioning::ApStack;
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+const LEASE_SECS: u32 = 3600;
+
+const BOOTP_OP_REQUEST: u8 = 1;
+const BOOTP_OP_REPLY: u8 = 2;
+const BOOTP_HEADER_LEN: usize = 236;
+const MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_END: u8 = 255;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPACK: u8 = 5;
+
+struct Request {
+    xid: [u8; 4],
+    chaddr: [u8; 6],
+    msg_type: u8,
+}
+
+/// Hands out `lease_ip` to whichever single client asks, with `server_ip` as
+/// both the DHCP server and the gateway/router option.
+#[embassy_executor::task]
+pub async fn run(stack: &'static ApStack, server_ip: Ipv4Address, lease_ip: Ipv4Address) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0u8; 512];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0u8; 512];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(SERVER_PORT).unwrap();
+
+    let mut buf = [0u8; 512];
+    loop {
+        let len = match socket.recv_from(&mut buf).await {
+            Ok((len, _meta)) => len,
+            Err(_) => continue,
+        };
+        let Some(request) = parse(&buf[..len]) else {
+            continue;
+        };
+
+        let reply_type = match request.msg_type {
+            DHCPDISCOVER => DHCPOFFER,
+            DHCPREQUEST => DHCPACK,
+            _ => continue,
+        };
+        println!("DHCP: replying {reply_type} to {:02x?}", request.chaddr);
+
+        let reply = build_reply(&request, reply_type, server_ip, lease_ip);
+        let endpoint = IpEndpoint::new(IpAddress::Ipv4(Ipv4Address::BROADCAST), CLIENT_PORT);
+        socket.send_to(&reply, endpoint).await.ok();
+    }
+}
+
+/// Pulls out just the fields needed to answer: the transaction id and client
+/// hardware address to echo back, and the message type option.
+fn parse(packet: &[u8]) -> Option<Request> {
+    if packet.len() < BOOTP_HEADER_LEN + MAGIC_COOKIE.len()
+        || packet[0] != BOOTP_OP_REQUEST
+        || packet[BOOTP_HEADER_LEN..BOOTP_HEADER_LEN + 4] != MAGIC_COOKIE
+    {
+        return None;
+    }
+
+    let xid = packet[4..8].try_into().ok()?;
+    let chaddr = packet[28..34].try_into().ok()?;
+
+    let mut msg_type = None;
+    let mut offset = BOOTP_HEADER_LEN + MAGIC_COOKIE.len();
+    while offset < packet.len() {
+        match packet[offset] {
+            OPT_END => break,
+            0 => offset += 1, // pad
+            code => {
+                let opt_len = *packet.get(offset + 1)? as usize;
+                let value = packet.get(offset + 2..offset + 2 + opt_len)?;
+                if code == OPT_MESSAGE_TYPE && opt_len == 1 {
+                    msg_type = Some(value[0]);
+                }
+                offset += 2 + opt_len;
+            }
+        }
+    }
+
+    Some(Request {
+        xid,
+        chaddr,
+        msg_type: msg_type?,
+    })
+}
+
+fn build_reply(
+    request: &Request,
+    msg_type: u8,
+    server_ip: Ipv4Address,
+    lease_ip: Ipv4Address,
+) -> heapless::Vec<u8, 300> {
+    let mut out = heapless::Vec::<u8, 300>::new();
+
+    out.push(BOOTP_OP_REPLY).ok();
+    out.push(1).ok(); // htype: ethernet
+    out.push(6).ok(); // hlen
+    out.push(0).ok(); // hops
+    out.extend_from_slice(&request.xid).ok();
+    out.extend_from_slice(&[0, 0]).ok(); // secs
+    out.extend_from_slice(&[0, 0]).ok(); // flags
+    out.extend_from_slice(&[0, 0, 0, 0]).ok(); // ciaddr
+    out.extend_from_slice(lease_ip.as_bytes()).ok(); // yiaddr
+    out.extend_from_slice(server_ip.as_bytes()).ok(); // siaddr
+    out.extend_from_slice(&[0, 0, 0, 0]).ok(); // giaddr
+    let mut chaddr = [0u8; 16];
+    chaddr[..6].copy_from_slice(&request.chaddr);
+    out.extend_from_slice(&chaddr).ok(); // chaddr
+    out.extend_from_slice(&[0u8; 64]).ok(); // sname
+    out.extend_from_slice(&[0u8; 128]).ok(); // file
+    out.extend_from_slice(&MAGIC_COOKIE).ok();
+
+    out.extend_from_slice(&[OPT_MESSAGE_TYPE, 1, msg_type]).ok();
+    out.extend_from_slice(&[OPT_SERVER_ID, 4]).ok();
+    out.extend_from_slice(server_ip.as_bytes()).ok();
+    out.extend_from_slice(&[OPT_SUBNET_MASK, 4, 255, 255, 255, 0]).ok();
+    out.extend_from_slice(&[OPT_ROUTER, 4]).ok();
+    out.extend_from_slice(server_ip.as_bytes()).ok();
+    out.extend_from_slice(&[OPT_LEASE_TIME, 4]).ok();
+    out.extend_from_slice(&LEASE_SECS.to_be_bytes()).ok();
+    out.push(OPT_END).ok();
+
+    out
+}
@@ -0,0 +1,221 @@
+//! Drives a 128x64 SSD1306 OLED on the shared I2C bus with `embedded-graphics`,
+//! giving each attendee a visible identity without needing the phone UI open:
+//! the badge's name, the station IP once DHCP resolves, a live Wi-Fi
+//! connection indicator, and the latest accelerometer reading.
+//!
+//! There's no off-the-shelf driver crate in this tree, so the panel is talked
+//! to directly over [`SharedI2c`] the same way [`crate::accelerometer`] talks
+//! to the LIS2DH12 - a small init command sequence, then GDDRAM writes.
+//! Unlike true e-paper, the OLED has no slow full-refresh waveform to avoid,
+//! but repainting costs an I2C transaction, so [`Display::flush`] diffs
+//! against the last frame and only pushes the smallest changed
+//! column/page window - a frame with nothing new to say costs nothing.
+
+use bhbadge2024::{lis2dh12::F32x3, shared_i2c::SharedI2c};
+use core::fmt::Write as _;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{
+    blocking_mutex::raw::NoopRawMutex,
+    pubsub::{Subscriber, WaitResult},
+};
+use embassy_time::{Duration, Timer};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    text::Text,
+    Drawable, Pixel,
+};
+use esp_wifi::wifi::WifiState;
+
+use crate::{net::Stack, webserver::ACCEL_CHANNEL_SUBS};
+
+const WIDTH: usize = 128;
+const HEIGHT: usize = 64;
+const PAGES: usize = HEIGHT / 8;
+const DISPLAY_ADDR: u8 = 0x3C;
+const REFRESH: Duration = Duration::from_millis(500);
+
+pub async fn init(
+    spawner: &embassy_executor::Spawner,
+    i2c: SharedI2c,
+    stack: &'static Stack,
+    name: &'static str,
+    accel_subscriber: Subscriber<'static, NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1>,
+) {
+    let mut display = Display::new(i2c);
+    display.init().await.unwrap();
+    spawner.must_spawn(display_task(display, stack, name, accel_subscriber));
+}
+
+#[embassy_executor::task]
+async fn display_task(
+    mut display: Display,
+    stack: &'static Stack,
+    name: &'static str,
+    mut accel_subscriber: Subscriber<'static, NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1>,
+) {
+    let mut last_accel = F32x3::default();
+
+    loop {
+        match select(accel_subscriber.next_message(), Timer::after(REFRESH)).await {
+            Either::First(WaitResult::Lagged(_)) => {}
+            Either::First(WaitResult::Message((dir, _temperature))) => last_accel = dir,
+            Either::Second(()) => {}
+        }
+
+        render(&mut display, stack, name, last_accel);
+        display.flush().await.unwrap();
+    }
+}
+
+fn render(display: &mut Display, stack: &Stack, name: &str, accel: F32x3) {
+    display.clear(BinaryColor::Off).ok();
+    let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+
+    Text::new(name, Point::new(0, 10), style)
+        .draw(display)
+        .ok();
+
+    let mut line = heapless::String::<32>::new();
+    match stack.config_v4() {
+        Some(config) => write!(line, "ip {}", config.address.address()).ok(),
+        None => write!(line, "ip ---").ok(),
+    };
+    Text::new(&line, Point::new(0, 24), style).draw(display).ok();
+
+    let wifi = match esp_wifi::wifi::get_wifi_state() {
+        WifiState::StaConnected => "wifi: connected",
+        WifiState::StaConnecting => "wifi: connecting",
+        WifiState::StaDisconnected => "wifi: disconnected",
+        _ => "wifi: ---",
+    };
+    Text::new(wifi, Point::new(0, 38), style).draw(display).ok();
+
+    let mut accel_line = heapless::String::<32>::new();
+    write!(accel_line, "xyz {:.1} {:.1} {:.1}", accel.x, accel.y, accel.z).ok();
+    Text::new(&accel_line, Point::new(0, 52), style)
+        .draw(display)
+        .ok();
+}
+
+/// A 128x64 monochrome framebuffer for an SSD1306 panel, addressable with
+/// `embedded-graphics` drawables.
+struct Display {
+    i2c: SharedI2c,
+    framebuffer: [u8; WIDTH * PAGES],
+    last_flushed: [u8; WIDTH * PAGES],
+}
+
+impl Display {
+    fn new(i2c: SharedI2c) -> Self {
+        Self {
+            i2c,
+            framebuffer: [0; WIDTH * PAGES],
+            // All-ones so the very first flush (an all-off framebuffer) is
+            // seen as a diff and actually gets pushed to the panel.
+            last_flushed: [0xFF; WIDTH * PAGES],
+        }
+    }
+
+    async fn init(&mut self) -> Result<(), esp_hal::i2c::Error> {
+        self.command(&[
+            0xAE, // display off
+            0xD5, 0x80, // clock divide ratio / oscillator frequency
+            0xA8, 0x3F, // multiplex ratio: 64
+            0xD3, 0x00, // display offset: none
+            0x40, // start line: 0
+            0x8D, 0x14, // charge pump: enable
+            0x20, 0x00, // memory addressing mode: horizontal
+            0xA1, // segment remap: column 127 is SEG0
+            0xC8, // COM output scan direction: remapped
+            0xDA, 0x12, // COM pins: alternative, no remap
+            0x81, 0xCF, // contrast
+            0xD9, 0xF1, // pre-charge period
+            0xDB, 0x40, // Vcomh deselect level
+            0xA4, // resume to RAM content display
+            0xA6, // normal (not inverted) display
+            0xAF, // display on
+        ])
+        .await
+    }
+
+    /// Pushes only the smallest column/page rectangle that changed since the
+    /// last flush, skipping the I2C transaction entirely if nothing did.
+    async fn flush(&mut self) -> Result<(), esp_hal::i2c::Error> {
+        let mut min_col = WIDTH;
+        let mut max_col = 0;
+        let mut min_page = PAGES;
+        let mut max_page = 0;
+
+        for page in 0..PAGES {
+            for col in 0..WIDTH {
+                let index = col + page * WIDTH;
+                if self.framebuffer[index] != self.last_flushed[index] {
+                    min_col = min_col.min(col);
+                    max_col = max_col.max(col);
+                    min_page = min_page.min(page);
+                    max_page = max_page.max(page);
+                }
+            }
+        }
+
+        if min_col > max_col {
+            return Ok(());
+        }
+
+        self.command(&[0x21, min_col as u8, max_col as u8]).await?;
+        self.command(&[0x22, min_page as u8, max_page as u8]).await?;
+
+        let mut payload = heapless::Vec::<u8, { 1 + WIDTH * PAGES }>::new();
+        payload.push(0x40).ok();
+        for page in min_page..=max_page {
+            for col in min_col..=max_col {
+                payload.push(self.framebuffer[col + page * WIDTH]).ok();
+            }
+        }
+        self.i2c.write(DISPLAY_ADDR, &payload).await?;
+
+        self.last_flushed = self.framebuffer;
+        Ok(())
+    }
+
+    async fn command(&mut self, bytes: &[u8]) -> Result<(), esp_hal::i2c::Error> {
+        let mut payload = heapless::Vec::<u8, 32>::new();
+        payload.push(0x00).ok();
+        payload.extend_from_slice(bytes).ok();
+        self.i2c.write(DISPLAY_ADDR, &payload).await
+    }
+}
+
+impl DrawTarget for Display {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x < 0 || point.x >= WIDTH as i32 || point.y < 0 || point.y >= HEIGHT as i32 {
+                continue;
+            }
+            let (x, y) = (point.x as usize, point.y as usize);
+            let index = x + (y / 8) * WIDTH;
+            let bit = 1 << (y % 8);
+            if color == BinaryColor::On {
+                self.framebuffer[index] |= bit;
+            } else {
+                self.framebuffer[index] &= !bit;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Display {
+    fn size(&self) -> Size {
+        Size::new(WIDTH as u32, HEIGHT as u32)
+    }
+}
@@ -0,0 +1,256 @@
+//! Minimal W5500 SPI Ethernet driver running in MACRAW mode, the wired
+//! fallback for [`crate::net::NetBackend::Ethernet`]. Talks to the chip
+//! register-by-register the same way [`bhbadge2024::lis2dh12`] talks to the
+//! accelerometer, just over SPI instead of I2C, and feeds raw frames into an
+//! `embassy-net-driver-channel` so the rest of the network stack only ever
+//! sees a [`embassy_net_driver::Driver`] impl - it has no idea there's a
+//! second MAC in the tree.
+//!
+//! Only MACRAW mode is implemented: no DHCP client, ARP or IP stack lives on
+//! the chip itself, all of that is smoltcp's job once frames make it onto the
+//! channel. This driver's only responsibility is moving whole Ethernet
+//! frames in and out of socket 0's ring buffers.
+
+use embassy_executor::Spawner;
+use embassy_net_driver_channel as channel;
+use embassy_time::Timer;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::spi::SpiBus;
+
+pub const MTU: usize = 1514;
+const N_RX_BUFFERS: usize = 4;
+const N_TX_BUFFERS: usize = 4;
+
+// Common register block (BSB 0b00000).
+const BSB_COMMON: u8 = 0x00;
+const SHAR: u16 = 0x0009; // source hardware (MAC) address, 6 bytes
+
+// Socket 0 register block (BSB 0b00001); only socket 0 is used, in MACRAW mode.
+const BSB_SOCKET0: u8 = 0x01;
+const SN_MR: u16 = 0x0000;
+const SN_CR: u16 = 0x0001;
+const SN_SR: u16 = 0x0003;
+const SN_RX_RSR: u16 = 0x0026;
+const SN_RX_RD: u16 = 0x0028;
+const SN_TX_FSR: u16 = 0x0020;
+const SN_TX_WR: u16 = 0x0024;
+
+const BSB_SOCKET0_TX_BUF: u8 = 0x02;
+const BSB_SOCKET0_RX_BUF: u8 = 0x03;
+
+// Neither Sn_RXBUF_SIZE nor Sn_TXBUF_SIZE is written here, so socket 0 keeps
+// the chip's reset default of 2 KiB per socket for both buffers.
+const RX_BUF_SIZE: u16 = 2048;
+const TX_BUF_SIZE: u16 = 2048;
+
+const SN_MR_MACRAW: u8 = 0x04;
+const SN_CR_OPEN: u8 = 0x01;
+const SN_CR_SEND: u8 = 0x20;
+const SN_CR_RECV: u8 = 0x40;
+const SN_SR_MACRAW: u8 = 0x42;
+
+/// SPI bus, chip-select and hardware-reset pins for the W5500, plus the MAC
+/// address to program into it. Generic over the `embedded-hal` traits rather
+/// than a concrete `esp_hal` peripheral, since the driver itself doesn't care
+/// which SPI instance it's wired to.
+pub struct SpiPins<SPI, CS, RST> {
+    pub spi: SPI,
+    pub cs: CS,
+    pub reset: RST,
+    pub mac: [u8; 6],
+}
+
+/// Resets and opens the chip in MACRAW mode, spawns the task that pumps
+/// frames between it and the returned channel device, and hands back the
+/// `Driver`-implementing half for [`crate::net::NetDriver::Ethernet`].
+pub async fn init<SPI, CS, RST>(
+    spawner: &Spawner,
+    pins: SpiPins<SPI, CS, RST>,
+) -> channel::Device<'static, MTU>
+where
+    SPI: SpiBus + 'static,
+    CS: OutputPin + 'static,
+    RST: OutputPin + 'static,
+{
+    let mut chip = W5500 {
+        spi: pins.spi,
+        cs: pins.cs,
+    };
+
+    let mut reset = pins.reset;
+    reset.set_low().ok();
+    Timer::after_millis(1).await;
+    reset.set_high().ok();
+    Timer::after_millis(2).await;
+
+    chip.write(BSB_COMMON, SHAR, &pins.mac).await;
+    chip.write(BSB_SOCKET0, SN_MR, &[SN_MR_MACRAW]).await;
+    chip.write(BSB_SOCKET0, SN_CR, &[SN_CR_OPEN]).await;
+    loop {
+        let mut status = [0u8; 1];
+        chip.read(BSB_SOCKET0, SN_SR, &mut status).await;
+        if status[0] == SN_SR_MACRAW {
+            break;
+        }
+        Timer::after_millis(1).await;
+    }
+
+    static STATE: static_cell::StaticCell<channel::State<MTU, N_RX_BUFFERS, N_TX_BUFFERS>> =
+        static_cell::StaticCell::new();
+    let state = STATE.init(channel::State::new());
+    let (runner, device) = channel::new(state, channel::driver::HardwareAddress::Ethernet(pins.mac));
+
+    spawner.must_spawn(mac_task(chip, runner));
+    device
+}
+
+#[embassy_executor::task]
+async fn mac_task(mut chip: W5500Dyn, mut runner: channel::Runner<'static, MTU>) {
+    let (mut state_chan, mut rx_chan, mut tx_chan) = runner.split();
+    state_chan.set_link_state(channel::driver::LinkState::Up);
+
+    loop {
+        let rx_pending = async {
+            loop {
+                let mut size = [0u8; 2];
+                chip.read(BSB_SOCKET0, SN_RX_RSR, &mut size).await;
+                if u16::from_be_bytes(size) > 0 {
+                    return;
+                }
+                Timer::after_millis(1).await;
+            }
+        };
+
+        match embassy_futures::select::select(rx_pending, tx_chan.tx_buf()).await {
+            embassy_futures::select::Either::First(()) => {
+                if let Some(buf) = rx_chan.rx_buf().await {
+                    let len = chip.recv_frame(buf).await;
+                    rx_chan.rx_done(len);
+                }
+            }
+            embassy_futures::select::Either::Second(buf) => {
+                chip.send_frame(buf).await;
+                tx_chan.tx_done();
+            }
+        }
+    }
+}
+
+/// The concrete SPI/CS types the single badge SPI bus uses; the `mac_task`
+/// above is a plain (not generic) embassy task, which embassy-executor
+/// requires, so the generic [`W5500`] is instantiated once here rather than
+/// per SPI implementation.
+type W5500Dyn = W5500<
+    esp_hal::spi::master::SpiDmaBus<'static, esp_hal::peripherals::SPI2, esp_hal::Async>,
+    esp_hal::gpio::Output<'static>,
+>;
+
+struct W5500<SPI, CS> {
+    spi: SPI,
+    cs: CS,
+}
+
+impl<SPI: SpiBus, CS: OutputPin> W5500<SPI, CS> {
+    async fn read(&mut self, block: u8, addr: u16, buf: &mut [u8]) {
+        let header = Self::header(block, addr, false);
+        self.cs.set_low().ok();
+        self.spi.write(&header).await.ok();
+        self.spi.read(buf).await.ok();
+        self.cs.set_high().ok();
+    }
+
+    async fn write(&mut self, block: u8, addr: u16, data: &[u8]) {
+        let header = Self::header(block, addr, true);
+        self.cs.set_low().ok();
+        self.spi.write(&header).await.ok();
+        self.spi.write(data).await.ok();
+        self.cs.set_high().ok();
+    }
+
+    /// The W5500's 3-byte SPI frame header: 16-bit address, then a control
+    /// byte of `[block select (5 bits) | read/write (1 bit) | mode (2 bits)]`.
+    /// Mode `00` is variable data length mode, so the data phase is just
+    /// however many bytes follow in the same chip-select-low transaction.
+    fn header(block: u8, addr: u16, write: bool) -> [u8; 3] {
+        let control = (block << 3) | ((write as u8) << 2);
+        let [hi, lo] = addr.to_be_bytes();
+        [hi, lo, control]
+    }
+
+    /// Copies the next queued RX frame out of socket 0's receive buffer into
+    /// `buf`, returning how many bytes were written. The W5500 prefixes each
+    /// MACRAW frame in the ring buffer with a 2-byte length header.
+    async fn recv_frame(&mut self, buf: &mut [u8]) -> usize {
+        let mut read_ptr_bytes = [0u8; 2];
+        self.read(BSB_SOCKET0, SN_RX_RD, &mut read_ptr_bytes).await;
+        let mut read_ptr = u16::from_be_bytes(read_ptr_bytes);
+
+        let mut len_header = [0u8; 2];
+        self.read_ring(BSB_SOCKET0_RX_BUF, read_ptr, &mut len_header, RX_BUF_SIZE)
+            .await;
+        let frame_len = (u16::from_be_bytes(len_header) as usize).saturating_sub(2);
+        read_ptr = read_ptr.wrapping_add(2);
+
+        let len = frame_len.min(buf.len());
+        self.read_ring(BSB_SOCKET0_RX_BUF, read_ptr, &mut buf[..len], RX_BUF_SIZE)
+            .await;
+        read_ptr = read_ptr.wrapping_add(len as u16);
+
+        self.write(BSB_SOCKET0, SN_RX_RD, &read_ptr.to_be_bytes()).await;
+        self.write(BSB_SOCKET0, SN_CR, &[SN_CR_RECV]).await;
+
+        len
+    }
+
+    async fn send_frame(&mut self, frame: &[u8]) {
+        // Wait until socket 0's TX free-size register reports room for the
+        // whole frame, then place it and kick SEND.
+        loop {
+            let mut free = [0u8; 2];
+            self.read(BSB_SOCKET0, SN_TX_FSR, &mut free).await;
+            if u16::from_be_bytes(free) as usize >= frame.len() {
+                break;
+            }
+            Timer::after_millis(1).await;
+        }
+        let mut write_ptr_bytes = [0u8; 2];
+        self.read(BSB_SOCKET0, SN_TX_WR, &mut write_ptr_bytes).await;
+        let mut write_ptr = u16::from_be_bytes(write_ptr_bytes);
+
+        self.write_ring(BSB_SOCKET0_TX_BUF, write_ptr, frame, TX_BUF_SIZE)
+            .await;
+        write_ptr = write_ptr.wrapping_add(frame.len() as u16);
+
+        self.write(BSB_SOCKET0, SN_TX_WR, &write_ptr.to_be_bytes()).await;
+        self.write(BSB_SOCKET0, SN_CR, &[SN_CR_SEND]).await;
+    }
+
+    /// `Sn_RX_RD`/`Sn_TX_WR` are free-running 16-bit counters that keep
+    /// advancing across the whole MACRAW session rather than resetting to 0
+    /// each time they wrap the physical ring buffer, so `ptr` has to be
+    /// masked down to a real buffer offset before use, and a read/write that
+    /// straddles the wrap point split into the two halves on either side of it.
+    async fn read_ring(&mut self, block: u8, ptr: u16, buf: &mut [u8], buf_size: u16) {
+        let offset = ptr & (buf_size - 1);
+        let until_wrap = (buf_size - offset) as usize;
+        if buf.len() <= until_wrap {
+            self.read(block, offset, buf).await;
+        } else {
+            let (first, second) = buf.split_at_mut(until_wrap);
+            self.read(block, offset, first).await;
+            self.read(block, 0, second).await;
+        }
+    }
+
+    async fn write_ring(&mut self, block: u8, ptr: u16, data: &[u8], buf_size: u16) {
+        let offset = ptr & (buf_size - 1);
+        let until_wrap = (buf_size - offset) as usize;
+        if data.len() <= until_wrap {
+            self.write(block, offset, data).await;
+        } else {
+            let (first, second) = data.split_at(until_wrap);
+            self.write(block, offset, first).await;
+            self.write(block, 0, second).await;
+        }
+    }
+}
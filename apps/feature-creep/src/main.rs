@@ -3,29 +3,33 @@
 #![feature(type_alias_impl_trait)]
 #![allow(long_running_const_eval)]
 
+mod accelerometer;
+mod animation;
+mod ble;
+mod dhcp;
+mod display;
+mod ethernet;
+mod mqtt;
+pub mod net;
+mod provisioning;
 pub mod webserver;
 mod webserver_file;
-pub mod wifi;
 
-use bhbadge2024::{
-    lis2dh12::{F32x3, Lis2dh12},
-    shared_i2c,
-    ws2812b::Ws2812b,
-};
+use bhbadge2024::{lis2dh12::F32x3, shared_i2c, storage::Storage, ws2812b::Ws2812b};
 use embassy_executor::Spawner;
-use embassy_sync::{
-    blocking_mutex::raw::NoopRawMutex,
-    pubsub::{PubSubChannel, Publisher},
-};
+use embassy_net::{IpAddress, Ipv4Address};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, pubsub::PubSubChannel};
 use embassy_time::Timer;
 use esp_hal::{
     clock::ClockControl, gpio::Io, i2c::I2C, interrupt::Priority, peripherals::Peripherals,
     prelude::*, rmt::Rmt, system::SystemControl, timer::timg::TimerGroup,
 };
 use esp_hal_embassy::InterruptExecutor;
+use esp_storage::FlashStorage;
 use esp_wifi::wifi::{AuthMethod, ClientConfiguration};
+use feature_creep_types::Gesture;
 use static_cell::StaticCell;
-use webserver::{AppState, WEB_TASK_POOL_SIZE};
+use webserver::{AppState, ACCEL_CHANNEL_SUBS, WEB_TASK_POOL_SIZE};
 
 #[macro_export]
 macro_rules! mk_static {
@@ -58,13 +62,48 @@ async fn main(spawner: Spawner) {
 
     let ws2812b = Ws2812b::new(&high_priority_spawner, rmt.channel0, io.pins.gpio10);
 
-    let stack = wifi::init(
-        &spawner,
-        ClientConfiguration {
-            ssid: "bornhack".try_into().unwrap(),
-            auth_method: AuthMethod::None,
+    let storage = Storage::new(FlashStorage::new());
+    ws2812b.restore_from_flash(&storage).await;
+
+    let saved_state = storage.load().await;
+    let client_config = match &saved_state {
+        Some(state) if !state.ssid.is_empty() => ClientConfiguration {
+            ssid: state.ssid.clone(),
+            password: state.password.clone(),
+            auth_method: AuthMethod::WPA2Personal,
             ..Default::default()
         },
+        // No saved credentials (factory-fresh badge): serve the AP provisioning
+        // page instead of guessing a network. `provisioning::run` reboots once
+        // credentials are submitted, so this arm never actually produces a value.
+        _ => {
+            provisioning::run(
+                &spawner,
+                &clocks,
+                peripherals.SYSTIMER,
+                peripherals.RNG,
+                peripherals.RADIO_CLK,
+                peripherals.WIFI,
+                storage,
+            )
+            .await
+        }
+    };
+
+    // Only the Wi-Fi backend is wired up on current badge hardware; the
+    // generic SPI/CS/reset types just need to be pinned to *something* so the
+    // unused `net::NetBackend::Ethernet` arm type-checks. A future badge
+    // revision with a W5500 on board would pass `NetBackend::Ethernet(...)`
+    // with its actual SPI peripheral and pins instead.
+    let backend: net::NetBackend<
+        esp_hal::spi::master::SpiDmaBus<'static, esp_hal::peripherals::SPI2, esp_hal::Async>,
+        esp_hal::gpio::Output<'static>,
+        esp_hal::gpio::Output<'static>,
+    > = net::NetBackend::Wifi(client_config);
+
+    let (stack, wifi_init) = net::init(
+        &spawner,
+        backend,
         &clocks,
         peripherals.SYSTIMER,
         peripherals.RNG,
@@ -73,11 +112,31 @@ async fn main(spawner: Spawner) {
     )
     .await;
 
-    let channel = PubSubChannel::<NoopRawMutex, (F32x3, f32), 1, WEB_TASK_POOL_SIZE, 1>::new();
-    let app_state: &'static AppState = mk_static!(AppState, AppState { ws2812b, channel });
+    let channel = PubSubChannel::<NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1>::new();
+    let gesture_channel = PubSubChannel::<NoopRawMutex, Gesture, 1, WEB_TASK_POOL_SIZE, 1>::new();
+    let app_state: &'static AppState = mk_static!(
+        AppState,
+        AppState {
+            ws2812b,
+            storage,
+            channel,
+            gesture_channel,
+        }
+    );
     let publisher = app_state.channel.publisher().unwrap();
+    let gesture_publisher = app_state.gesture_channel.publisher().unwrap();
 
     webserver::init(&spawner, stack, app_state).await;
+    // BLE shares the Wi-Fi radio's init handle, so it's only available when
+    // that backend is the one actually running.
+    if let Some(wifi_init) = wifi_init {
+        ble::init(&spawner, wifi_init, peripherals.BT, app_state).await;
+    }
+    animation::init(&spawner, ws2812b);
+
+    // Placeholder until AP provisioning can hand us a broker address at runtime.
+    let mqtt_broker = IpAddress::Ipv4(Ipv4Address::new(10, 42, 0, 1));
+    mqtt::init(&spawner, stack, mqtt_broker, 1883, "badge2024", app_state).await;
 
     let shared_i2c = shared_i2c::SharedI2c::new(I2C::new_async(
         peripherals.I2C0,
@@ -87,7 +146,16 @@ async fn main(spawner: Spawner) {
         &clocks,
     ));
 
-    init_lis2dh12(&spawner, shared_i2c, publisher).await;
+    accelerometer::init(&spawner, shared_i2c, publisher, gesture_publisher).await;
+
+    display::init(
+        &spawner,
+        shared_i2c,
+        stack,
+        "badge2024",
+        app_state.channel.subscriber().unwrap(),
+    )
+    .await;
 
     // Badge:
     //   sda/scl: io6/io7
@@ -116,47 +184,3 @@ async fn main(spawner: Spawner) {
         Timer::after_micros(500).await;
     }
 }
-
-async fn init_lis2dh12(
-    spawner: &Spawner,
-    shared_i2c: shared_i2c::SharedI2c,
-    publisher: Publisher<'static, NoopRawMutex, (F32x3, f32), 1, WEB_TASK_POOL_SIZE, 1>,
-) {
-    let mut lis2dh12 = Lis2dh12::new(
-        shared_i2c,
-        bhbadge2024::lis2dh12::SlaveAddr::Alternative(true),
-    )
-    .await
-    .unwrap();
-
-    lis2dh12.reset().await.unwrap();
-    lis2dh12
-        .set_odr(bhbadge2024::lis2dh12::Odr::Hz400)
-        .await
-        .unwrap();
-    lis2dh12
-        .set_mode(bhbadge2024::lis2dh12::Mode::Normal)
-        .await
-        .unwrap();
-    lis2dh12
-        .set_fs(bhbadge2024::lis2dh12::FullScale::G16)
-        .await
-        .unwrap();
-    lis2dh12.enable_axis((true, true, true)).await.unwrap();
-    lis2dh12.enable_temp(true).await.unwrap();
-
-    spawner.must_spawn(read_accelerometer(lis2dh12, publisher));
-}
-
-#[embassy_executor::task]
-async fn read_accelerometer(
-    mut lis2dh12: Lis2dh12,
-    publisher: Publisher<'static, NoopRawMutex, (F32x3, f32), 1, WEB_TASK_POOL_SIZE, 1>,
-) {
-    loop {
-        let dir = lis2dh12.accel_norm().await.unwrap();
-        let temperature = lis2dh12.get_temp_outf().await.unwrap();
-        publisher.publish_immediate((dir, temperature + 20.0));
-        Timer::after_millis(500).await;
-    }
-}
@@ -0,0 +1,369 @@
+//! Minimal MQTT 3.1.1 bridge over the existing Wi-Fi stack, parallel to the
+//! websocket in [`crate::webserver`] and the GATT service in [`crate::ble`].
+//!
+//! Publishes `Message::Accelerometer` (the same compact JSON wire format the
+//! other two bridges use) to `badge/<id>/state` whenever the accelerometer
+//! channel fires, and `Message::CurrentColors` there only in reply to
+//! `QueryColors`; LED colour changes are not published by themselves. Applies
+//! `Command::ChangeColor`/`QueryColors`/`SaveState` received on `badge/<id>/cmd`. Only
+//! the handful of packet types the bridge actually needs are implemented by
+//! hand: CONNECT/CONNACK, PUBLISH at QoS 0, one SUBSCRIBE, and
+//! PINGREQ/PINGRESP to hold the connection open across the keepalive.
+
+use core::fmt::Write as _;
+
+use bhbadge2024::{lis2dh12::F32x3, storage::Storage, ws2812b::Ws2812b};
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_net::{tcp::TcpSocket, IpAddress};
+use embassy_sync::{
+    blocking_mutex::raw::NoopRawMutex,
+    mutex::Mutex,
+    pubsub::{Subscriber, WaitResult},
+};
+use embassy_time::{Duration, Timer};
+use embedded_io_async::{Read, Write};
+use esp_println::println;
+use feature_creep_types::{Command, Message};
+
+use crate::{
+    webserver::{AppState, ACCEL_CHANNEL_SUBS},
+    net::Stack,
+};
+
+const KEEPALIVE: Duration = Duration::from_secs(60);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+type TopicString = heapless::String<40>;
+
+/// Spawns the MQTT bridge task, reconnecting to `(broker, port)` whenever the
+/// connection drops.
+pub async fn init(
+    spawner: &Spawner,
+    stack: &'static Stack,
+    broker: IpAddress,
+    port: u16,
+    id: &'static str,
+    app_state: &'static AppState,
+) {
+    spawner.must_spawn(mqtt_task(stack, broker, port, id, app_state));
+}
+
+#[embassy_executor::task]
+async fn mqtt_task(
+    stack: &'static Stack,
+    broker: IpAddress,
+    port: u16,
+    id: &'static str,
+    app_state: &'static AppState,
+) {
+    let mut rx_buffer = [0u8; 1024];
+    let mut tx_buffer = [0u8; 1024];
+
+    let mut state_topic = TopicString::new();
+    write!(state_topic, "badge/{id}/state").ok();
+    let mut cmd_topic = TopicString::new();
+    write!(cmd_topic, "badge/{id}/cmd").ok();
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        if let Err(e) = socket.connect((broker, port)).await {
+            println!("MQTT: connect failed: {e:?}");
+            Timer::after(RECONNECT_DELAY).await;
+            continue;
+        }
+
+        if let Err(e) = connect(&mut socket, id).await {
+            println!("MQTT: CONNECT failed: {e:?}");
+            Timer::after(RECONNECT_DELAY).await;
+            continue;
+        }
+
+        if let Err(e) = subscribe(&mut socket, &cmd_topic).await {
+            println!("MQTT: SUBSCRIBE failed: {e:?}");
+            Timer::after(RECONNECT_DELAY).await;
+            continue;
+        }
+
+        println!("MQTT: connected to broker, bridging {state_topic} / {cmd_topic}");
+
+        let subscriber: Subscriber<'static, NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1> =
+            app_state.channel.subscriber().unwrap();
+
+        if let Err(e) = session(
+            &mut socket,
+            subscriber,
+            &state_topic,
+            app_state.ws2812b,
+            app_state.storage,
+        )
+        .await
+        {
+            println!("MQTT: connection lost: {e:?}");
+        }
+
+        Timer::after(RECONNECT_DELAY).await;
+    }
+}
+
+/// Drives the connection until either side errors out. The read side
+/// (`read_publish`'s `read_exact` loop) can't safely be cancelled mid-read
+/// without losing already-consumed bytes off the stream, so it runs on its
+/// own loop rather than racing a `select` against the subscriber/keepalive
+/// timer the way the publish side does. The two loops share the socket's
+/// write half through a mutex since both need to publish back to the broker.
+async fn session(
+    socket: &mut TcpSocket<'_>,
+    mut subscriber: Subscriber<'static, NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1>,
+    state_topic: &str,
+    ws2812b: Ws2812b,
+    storage: Storage,
+) -> Result<(), embassy_net::tcp::Error> {
+    let (mut reader, writer) = socket.split();
+    let writer = Mutex::<NoopRawMutex, _>::new(writer);
+
+    let publish_loop = async {
+        loop {
+            match select(subscriber.next_message(), Timer::after(KEEPALIVE / 2)).await {
+                Either::First(WaitResult::Lagged(_)) => {}
+                Either::First(WaitResult::Message((dir, temperature))) => {
+                    let mut buffer = [0u8; 256];
+                    let len = serde_json_core::to_slice(
+                        &Message::Accelerometer([dir.x, dir.y, dir.z, temperature]),
+                        &mut buffer,
+                    )
+                    .unwrap();
+                    publish(&mut *writer.lock().await, state_topic, &buffer[..len]).await?;
+                }
+                Either::Second(()) => {
+                    writer.lock().await.write_all(&[0xC0, 0x00]).await?;
+                }
+            }
+        }
+    };
+
+    let read_loop = async {
+        let mut buffer = [0u8; 256];
+        loop {
+            let payload_len = read_publish(&mut reader, &mut buffer).await?;
+            match serde_json_core::from_slice(&buffer[..payload_len]) {
+                Ok((Command::ChangeColor { index, rgb }, _)) if (index as usize) < 16 => {
+                    crate::animation::notify_manual_override();
+                    ws2812b.set_pixel(index as usize, rgb);
+                }
+                Ok((Command::QueryColors, _)) => {
+                    let mut res = [(0u8, 0u8, 0u8); 16];
+                    ws2812b.with_frame_buffer(|f| {
+                        for (i, pix) in f.raw_mut().iter().enumerate() {
+                            res[i] = (pix[1], pix[0], pix[2]);
+                        }
+                    });
+                    let len =
+                        serde_json_core::to_slice(&Message::CurrentColors(res), &mut buffer)
+                            .unwrap();
+                    publish(&mut *writer.lock().await, state_topic, &buffer[..len]).await?;
+                }
+                Ok((Command::SaveState, _)) => {
+                    ws2812b.save_to_flash(&storage).await;
+                }
+                Ok((Command::SetFullScale(range), _)) => {
+                    crate::accelerometer::set_range(range);
+                }
+                Ok((Command::SetBrightness(brightness), _)) => {
+                    ws2812b.set_brightness(brightness);
+                }
+                Ok((command, _)) => {
+                    println!("MQTT: unexpected command: {command:?}");
+                }
+                Err(e) => println!("MQTT: could not parse command: {e:?}"),
+            }
+        }
+    };
+
+    match select(publish_loop, read_loop).await {
+        Either::First(result) => result,
+        Either::Second(result) => result,
+    }
+}
+
+async fn connect(socket: &mut TcpSocket<'_>, client_id: &str) -> Result<(), embassy_net::tcp::Error> {
+    let mut variable_header = heapless::Vec::<u8, 16>::new();
+    variable_header.extend_from_slice(&4u16.to_be_bytes()).ok();
+    variable_header.extend_from_slice(b"MQTT").ok();
+    variable_header.push(4).ok(); // protocol level
+    variable_header.push(0x02).ok(); // connect flags: clean session, no will/credentials
+    variable_header
+        .extend_from_slice(&(KEEPALIVE.as_secs() as u16).to_be_bytes())
+        .ok();
+
+    let mut payload = heapless::Vec::<u8, 32>::new();
+    payload
+        .extend_from_slice(&(client_id.len() as u16).to_be_bytes())
+        .ok();
+    payload.extend_from_slice(client_id.as_bytes()).ok();
+
+    send_packet(socket, 0x10, &variable_header, &payload).await?;
+
+    let mut header = [0u8; 1];
+    read_exact(socket, &mut header).await?;
+    let remaining = read_remaining_length(socket).await?;
+    let mut ack = [0u8; 2];
+    read_exact(socket, &mut ack[..remaining.min(2)]).await?;
+    if ack[1] != 0 {
+        println!("MQTT: broker rejected CONNECT, return code {}", ack[1]);
+    }
+
+    Ok(())
+}
+
+async fn subscribe(socket: &mut TcpSocket<'_>, topic: &str) -> Result<(), embassy_net::tcp::Error> {
+    let mut variable_header = heapless::Vec::<u8, 2>::new();
+    variable_header.extend_from_slice(&1u16.to_be_bytes()).ok(); // packet id
+
+    let mut payload = heapless::Vec::<u8, 48>::new();
+    payload
+        .extend_from_slice(&(topic.len() as u16).to_be_bytes())
+        .ok();
+    payload.extend_from_slice(topic.as_bytes()).ok();
+    payload.push(0x00).ok(); // requested QoS 0
+
+    send_packet(socket, 0x82, &variable_header, &payload).await?;
+
+    // SUBACK: fixed header, remaining length, packet id, one return code per filter.
+    let mut header = [0u8; 1];
+    read_exact(socket, &mut header).await?;
+    let remaining = read_remaining_length(socket).await?;
+    let mut rest = [0u8; 8];
+    read_exact(socket, &mut rest[..remaining.min(8)]).await?;
+
+    Ok(())
+}
+
+async fn publish<W: Write<Error = embassy_net::tcp::Error>>(
+    socket: &mut W,
+    topic: &str,
+    payload: &[u8],
+) -> Result<(), embassy_net::tcp::Error> {
+    let mut variable_header = heapless::Vec::<u8, 40>::new();
+    variable_header
+        .extend_from_slice(&(topic.len() as u16).to_be_bytes())
+        .ok();
+    variable_header.extend_from_slice(topic.as_bytes()).ok();
+
+    send_packet(socket, 0x30, &variable_header, payload).await
+}
+
+/// Waits for the next PUBLISH on the subscribed topic and copies its payload
+/// into `buffer`, returning how many bytes were written. Other packet types
+/// (only PINGRESP and SUBACK should show up here after the handshake) are
+/// read and discarded, as is a PUBLISH whose topic is longer than we ever
+/// subscribe to.
+async fn read_publish<R: Read<Error = embassy_net::tcp::Error>>(
+    socket: &mut R,
+    buffer: &mut [u8; 256],
+) -> Result<usize, embassy_net::tcp::Error> {
+    loop {
+        let mut header = [0u8; 1];
+        read_exact(socket, &mut header).await?;
+        let remaining = read_remaining_length(socket).await?;
+
+        if header[0] & 0xF0 != 0x30 {
+            // Not a PUBLISH (e.g. PINGRESP) - drain and keep waiting.
+            discard(socket, remaining).await?;
+            continue;
+        }
+
+        let mut topic_len = [0u8; 2];
+        read_exact(socket, &mut topic_len).await?;
+        let topic_len = u16::from_be_bytes(topic_len) as usize;
+        let mut topic = [0u8; 64];
+        if topic_len > topic.len() {
+            // Longer than any topic we subscribe to - not for us, discard the rest
+            // of the packet instead of overrunning `topic`.
+            discard(socket, remaining - 2 - topic_len).await?;
+            continue;
+        }
+        read_exact(socket, &mut topic[..topic_len]).await?;
+
+        let payload_len = remaining - 2 - topic_len;
+        let payload_len = payload_len.min(buffer.len());
+        read_exact(socket, &mut buffer[..payload_len]).await?;
+        return Ok(payload_len);
+    }
+}
+
+async fn discard<R: Read<Error = embassy_net::tcp::Error>>(
+    socket: &mut R,
+    len: usize,
+) -> Result<(), embassy_net::tcp::Error> {
+    let mut buf = [0u8; 8];
+    let mut left = len;
+    while left > 0 {
+        let n = left.min(buf.len());
+        read_exact(socket, &mut buf[..n]).await?;
+        left -= n;
+    }
+    Ok(())
+}
+
+async fn send_packet<W: Write<Error = embassy_net::tcp::Error>>(
+    socket: &mut W,
+    fixed_header: u8,
+    variable_header: &[u8],
+    payload: &[u8],
+) -> Result<(), embassy_net::tcp::Error> {
+    let mut remaining_length = heapless::Vec::<u8, 4>::new();
+    encode_remaining_length(variable_header.len() + payload.len(), &mut remaining_length);
+
+    socket.write_all(&[fixed_header]).await?;
+    socket.write_all(&remaining_length).await?;
+    socket.write_all(variable_header).await?;
+    socket.write_all(payload).await?;
+    Ok(())
+}
+
+fn encode_remaining_length(mut len: usize, out: &mut heapless::Vec<u8, 4>) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte).ok();
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+async fn read_remaining_length<R: Read<Error = embassy_net::tcp::Error>>(
+    socket: &mut R,
+) -> Result<usize, embassy_net::tcp::Error> {
+    let mut multiplier = 1usize;
+    let mut value = 0usize;
+    loop {
+        let mut byte = [0u8; 1];
+        read_exact(socket, &mut byte).await?;
+        value += (byte[0] & 0x7F) as usize * multiplier;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        multiplier *= 128;
+    }
+    Ok(value)
+}
+
+async fn read_exact<R: Read<Error = embassy_net::tcp::Error>>(
+    socket: &mut R,
+    buf: &mut [u8],
+) -> Result<(), embassy_net::tcp::Error> {
+    let mut read = 0;
+    while read < buf.len() {
+        let n = socket.read(&mut buf[read..]).await?;
+        if n == 0 {
+            return Err(embassy_net::tcp::Error::ConnectionReset);
+        }
+        read += n;
+    }
+    Ok(())
+}
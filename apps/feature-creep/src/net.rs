@@ -0,0 +1,237 @@
+//! Network bring-up, parameterized over the physical link so the rest of the
+//! firmware (picoserve, the websocket, MQTT) only ever depends on
+//! `&'static Stack` and never on which MAC is underneath it.
+//!
+//! Two backends exist: the on-board Wi-Fi radio, the only one wired up on
+//! current badge hardware, and a W5500 SPI Ethernet chip in MACRAW mode (see
+//! [`crate::ethernet`]) for badges at venues too noisy for 2.4GHz. [`NetDriver`]
+//! is a thin enum over both so `embassy_net::Stack` only needs to be
+//! instantiated once; [`crate::ble`] still needs the radio init handle
+//! directly, so it's only available when the Wi-Fi backend was chosen.
+
+use core::task::Context;
+
+use embassy_executor::Spawner;
+use embassy_net::{Config, StackResources};
+use embassy_net_driver::{Capabilities, Driver, HardwareAddress, LinkState, RxToken, TxToken};
+use embassy_net_driver_channel as channel;
+use embassy_time::{Duration, Timer};
+use esp_hal::{
+    clock::Clocks,
+    peripheral::Peripheral,
+    peripherals::{RNG, SYSTIMER},
+    rng::Rng,
+};
+use esp_println::println;
+use esp_wifi::{
+    initialize,
+    wifi::{
+        ClientConfiguration, Configuration, WifiController, WifiDevice, WifiEvent, WifiStaDevice,
+        WifiState,
+    },
+    EspWifiInitFor, EspWifiInitialization,
+};
+
+use crate::{ethernet, mk_static, webserver::WEB_TASK_POOL_SIZE};
+
+pub type Stack = embassy_net::Stack<NetDriver>;
+
+/// Chosen once at boot: either join the badge's usual Wi-Fi network, or drive
+/// a wired W5500 instead.
+pub enum NetBackend<SPI, CS, RST> {
+    Wifi(ClientConfiguration),
+    Ethernet(ethernet::SpiPins<SPI, CS, RST>),
+}
+
+/// A network driver is either the radio or the wired MAC; `embassy_net::Stack`
+/// only takes one concrete [`Driver`] type, so this enum provides it by
+/// delegating every call to whichever backend is actually running.
+pub enum NetDriver {
+    Wifi(WifiDevice<'static, WifiStaDevice>),
+    Ethernet(channel::Device<'static, { ethernet::MTU }>),
+}
+
+pub enum NetRxToken<'a> {
+    Wifi(<WifiDevice<'static, WifiStaDevice> as Driver>::RxToken<'a>),
+    Ethernet(<channel::Device<'static, { ethernet::MTU }> as Driver>::RxToken<'a>),
+}
+
+pub enum NetTxToken<'a> {
+    Wifi(<WifiDevice<'static, WifiStaDevice> as Driver>::TxToken<'a>),
+    Ethernet(<channel::Device<'static, { ethernet::MTU }> as Driver>::TxToken<'a>),
+}
+
+impl RxToken for NetRxToken<'_> {
+    fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, f: F) -> R {
+        match self {
+            NetRxToken::Wifi(t) => t.consume(f),
+            NetRxToken::Ethernet(t) => t.consume(f),
+        }
+    }
+}
+
+impl TxToken for NetTxToken<'_> {
+    fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+        match self {
+            NetTxToken::Wifi(t) => t.consume(len, f),
+            NetTxToken::Ethernet(t) => t.consume(len, f),
+        }
+    }
+}
+
+impl Driver for NetDriver {
+    type RxToken<'a> = NetRxToken<'a> where Self: 'a;
+    type TxToken<'a> = NetTxToken<'a> where Self: 'a;
+
+    fn receive(&mut self, cx: &mut Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        match self {
+            NetDriver::Wifi(d) => d
+                .receive(cx)
+                .map(|(rx, tx)| (NetRxToken::Wifi(rx), NetTxToken::Wifi(tx))),
+            NetDriver::Ethernet(d) => d
+                .receive(cx)
+                .map(|(rx, tx)| (NetRxToken::Ethernet(rx), NetTxToken::Ethernet(tx))),
+        }
+    }
+
+    fn transmit(&mut self, cx: &mut Context) -> Option<Self::TxToken<'_>> {
+        match self {
+            NetDriver::Wifi(d) => d.transmit(cx).map(NetTxToken::Wifi),
+            NetDriver::Ethernet(d) => d.transmit(cx).map(NetTxToken::Ethernet),
+        }
+    }
+
+    fn link_state(&mut self, cx: &mut Context) -> LinkState {
+        match self {
+            NetDriver::Wifi(d) => d.link_state(cx),
+            NetDriver::Ethernet(d) => d.link_state(cx),
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        match self {
+            NetDriver::Wifi(d) => d.capabilities(),
+            NetDriver::Ethernet(d) => d.capabilities(),
+        }
+    }
+
+    fn hardware_address(&self) -> HardwareAddress {
+        match self {
+            NetDriver::Wifi(d) => d.hardware_address(),
+            NetDriver::Ethernet(d) => d.hardware_address(),
+        }
+    }
+}
+
+/// Brings up whichever backend `NetBackend` selects and returns the running
+/// network stack. For the Wi-Fi backend, also returns the underlying radio
+/// init handle so [`crate::ble`] can share it; the Ethernet backend doesn't
+/// use the radio at all, so BLE simply isn't available on those badges.
+pub async fn init<SPI, CS, RST>(
+    spawner: &Spawner,
+    backend: NetBackend<SPI, CS, RST>,
+    clocks: &Clocks<'_>,
+    systimer: impl Peripheral<P = SYSTIMER>,
+    rng: impl Peripheral<P = RNG>,
+    radio_clocks: esp_hal::peripherals::RADIO_CLK,
+    wifi: esp_hal::peripherals::WIFI,
+) -> (&'static Stack, Option<&'static EspWifiInitialization>)
+where
+    SPI: embedded_hal_async::spi::SpiBus + 'static,
+    CS: embedded_hal::digital::OutputPin + 'static,
+    RST: embedded_hal::digital::OutputPin + 'static,
+{
+    let mut rng = Rng::new(rng);
+    let seed = (rng.random() as u64) << 32 | rng.random() as u64;
+
+    let (driver, wifi_init) = match backend {
+        NetBackend::Wifi(client_config) => {
+            let timer = esp_hal::timer::systimer::SystemTimer::new(systimer).alarm0;
+            let init = mk_static!(
+                EspWifiInitialization,
+                initialize(EspWifiInitFor::WifiBle, timer, rng, radio_clocks, clocks).unwrap()
+            );
+
+            let (wifi_interface, controller) =
+                esp_wifi::wifi::new_with_mode(init, wifi, WifiStaDevice).unwrap();
+
+            spawner.spawn(connection(client_config, controller)).ok();
+            (NetDriver::Wifi(wifi_interface), Some(init))
+        }
+        NetBackend::Ethernet(pins) => {
+            let device = ethernet::init(spawner, pins).await;
+            (NetDriver::Ethernet(device), None)
+        }
+    };
+
+    let config = Config::dhcpv4(Default::default());
+    let stack = &*mk_static!(
+        Stack,
+        Stack::new(
+            driver,
+            config,
+            mk_static!(
+                StackResources<{ WEB_TASK_POOL_SIZE + 1 }>,
+                StackResources::<{ WEB_TASK_POOL_SIZE + 1 }>::new()
+            ),
+            seed
+        )
+    );
+
+    spawner.spawn(net_task(stack)).ok();
+
+    loop {
+        if stack.is_link_up() {
+            break;
+        }
+        Timer::after(Duration::from_millis(500)).await;
+    }
+
+    println!("Waiting to get IP address...");
+    loop {
+        if let Some(config) = stack.config_v4() {
+            println!("Got IP: {}", config.address);
+            break;
+        }
+        Timer::after(Duration::from_millis(500)).await;
+    }
+
+    (stack, wifi_init)
+}
+
+#[embassy_executor::task]
+async fn connection(config: ClientConfiguration, mut controller: WifiController<'static>) {
+    println!("start connection task");
+    println!("Device capabilities: {:?}", controller.get_capabilities());
+    loop {
+        match esp_wifi::wifi::get_wifi_state() {
+            WifiState::StaConnected => {
+                // wait until we're no longer connected
+                controller.wait_for_event(WifiEvent::StaDisconnected).await;
+                Timer::after(Duration::from_millis(5000)).await
+            }
+            _ => {}
+        }
+        if !matches!(controller.is_started(), Ok(true)) {
+            let client_config = Configuration::Client(config.clone());
+            controller.set_configuration(&client_config).unwrap();
+            println!("Starting wifi");
+            controller.start().await.unwrap();
+            println!("Wifi started!");
+        }
+        println!("About to connect...");
+
+        match controller.connect().await {
+            Ok(_) => println!("Wifi connected!"),
+            Err(e) => {
+                println!("Failed to connect to wifi: {e:?}");
+                Timer::after(Duration::from_millis(5000)).await
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn net_task(stack: &'static Stack) {
+    stack.run().await
+}
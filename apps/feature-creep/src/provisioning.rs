@@ -0,0 +1,308 @@
+//! AP-mode Wi-Fi provisioning for a badge that has no saved credentials yet.
+//!
+//! Brings the radio up as its own access point (`badge2024-setup`, open), and
+//! serves a tiny config page over the same `picoserve` stack [`crate::webserver`]
+//! uses on the AP's gateway IP. The page lists nearby networks (via the
+//! controller's scan) and posts back an SSID/password, which we persist with
+//! [`Storage::save`] and then reboot into, so `main` picks them up as a normal
+//! station-mode `ClientConfiguration` on the next boot.
+//!
+//! [`crate::dhcp`] hands out the one lease attendees need to reach the
+//! config page automatically, so this is good enough to get a badge onto a
+//! conference network once, not a general-purpose captive portal.
+
+use bhbadge2024::storage::Storage;
+use embassy_executor::Spawner;
+use embassy_net::{Config, Ipv4Address, Ipv4Cidr, Stack, StackResources, StaticConfigV4};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+use esp_hal::{
+    clock::Clocks,
+    peripheral::Peripheral,
+    peripherals::{RADIO_CLK, RNG, SYSTIMER, WIFI},
+    rng::Rng,
+};
+use esp_println::println;
+use esp_wifi::{
+    initialize,
+    wifi::{AccessPointConfiguration, AuthMethod, Configuration, WifiApDevice, WifiController},
+    EspWifiInitFor,
+};
+use picoserve::{
+    extract::{Json, State},
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{mk_static, webserver_file::File};
+
+pub(crate) type ApStack = Stack<esp_wifi::wifi::WifiDevice<'static, WifiApDevice>>;
+
+const AP_SSID: &str = "badge2024-setup";
+const GATEWAY: Ipv4Address = Ipv4Address::new(192, 168, 71, 1);
+const LEASE: Ipv4Address = Ipv4Address::new(192, 168, 71, 50);
+const TASK_POOL_SIZE: usize = 1;
+
+const PAGE: &str = r#"<!doctype html>
+<html><head><meta charset="utf-8"><title>badge2024 setup</title></head>
+<body>
+<h1>Connect badge2024 to Wi-Fi</h1>
+<ul id="networks">Scanning...</ul>
+<form id="form">
+  <input name="ssid" id="ssid" placeholder="SSID" required>
+  <input name="password" id="password" placeholder="Password" type="password">
+  <button type="submit">Connect</button>
+</form>
+<script>
+fetch('/scan').then(r => r.json()).then(nets => {
+  document.getElementById('networks').innerHTML = nets
+    .map(n => `<li onclick="document.getElementById('ssid').value='${n.ssid}'">${n.ssid} (${n.rssi} dBm, ${n.auth})</li>`)
+    .join('');
+});
+document.getElementById('form').addEventListener('submit', e => {
+  e.preventDefault();
+  const body = JSON.stringify({
+    ssid: document.getElementById('ssid').value,
+    password: document.getElementById('password').value,
+  });
+  fetch('/provision', { method: 'POST', headers: { 'Content-Type': 'application/json' }, body })
+    .then(() => document.body.innerHTML = '<p>Saved. The badge is rebooting...</p>');
+});
+</script>
+</body></html>"#;
+
+#[derive(Serialize)]
+struct ScannedNetwork<'a> {
+    ssid: &'a str,
+    rssi: i8,
+    auth: &'static str,
+}
+
+#[derive(Deserialize)]
+struct ProvisionForm {
+    ssid: heapless::String<32>,
+    password: heapless::String<64>,
+}
+
+struct ProvisioningState {
+    controller: Mutex<NoopRawMutex, WifiController<'static>>,
+    storage: Storage,
+    submitted: Signal<NoopRawMutex, ()>,
+}
+
+/// Runs AP-mode provisioning to completion. Only returns (by rebooting the
+/// MCU) once a set of credentials has been submitted and saved - call this
+/// instead of [`crate::net::init`] when [`Storage::load`] finds no SSID.
+pub async fn run(
+    spawner: &Spawner,
+    clocks: &Clocks<'_>,
+    systimer: impl Peripheral<P = SYSTIMER>,
+    rng: impl Peripheral<P = RNG>,
+    radio_clocks: RADIO_CLK,
+    wifi: WIFI,
+    storage: Storage,
+) -> ! {
+    let timer = esp_hal::timer::systimer::SystemTimer::new(systimer).alarm0;
+    let mut rng = Rng::new(rng);
+    let seed = (rng.random() as u64) << 32 | rng.random() as u64;
+
+    let init = mk_static!(
+        esp_wifi::EspWifiInitialization,
+        initialize(EspWifiInitFor::WifiBle, timer, rng, radio_clocks, clocks).unwrap()
+    );
+
+    let (wifi_interface, mut controller) =
+        esp_wifi::wifi::new_with_mode(init, wifi, WifiApDevice).unwrap();
+
+    controller
+        .set_configuration(&Configuration::AccessPoint(AccessPointConfiguration {
+            ssid: AP_SSID.try_into().unwrap(),
+            auth_method: AuthMethod::None,
+            ..Default::default()
+        }))
+        .unwrap();
+    controller.start().await.unwrap();
+
+    let config = Config::ipv4_static(StaticConfigV4 {
+        address: Ipv4Cidr::new(GATEWAY, 24),
+        gateway: Some(GATEWAY),
+        dns_servers: Default::default(),
+    });
+
+    let stack = &*mk_static!(
+        ApStack,
+        Stack::new(
+            wifi_interface,
+            config,
+            mk_static!(
+                // +1 for the web task pool's sockets, +1 for the DHCP task's UDP socket.
+                StackResources<{ TASK_POOL_SIZE + 2 }>,
+                StackResources::<{ TASK_POOL_SIZE + 2 }>::new()
+            ),
+            seed
+        )
+    );
+    spawner.must_spawn(net_task(stack));
+    spawner.must_spawn(crate::dhcp::run(stack, GATEWAY, LEASE));
+
+    println!("Provisioning: serving setup page on {GATEWAY} as AP {AP_SSID}");
+
+    let state: &'static ProvisioningState = mk_static!(
+        ProvisioningState,
+        ProvisioningState {
+            controller: Mutex::new(controller),
+            storage,
+            submitted: Signal::new(),
+        }
+    );
+
+    static APP: static_cell::StaticCell<App> = static_cell::StaticCell::new();
+    let app = APP.init(make_app());
+    static CONFIG: static_cell::StaticCell<picoserve::Config<embassy_time::Duration>> =
+        static_cell::StaticCell::new();
+    let config = CONFIG.init(
+        picoserve::Config::new(picoserve::Timeouts {
+            start_read_request: Some(Duration::from_secs(5)),
+            read_request: Some(Duration::from_secs(1)),
+            write: Some(Duration::from_secs(1)),
+        })
+        .keep_connection_alive(),
+    );
+
+    for id in 0..TASK_POOL_SIZE {
+        spawner.must_spawn(web_task(id, stack, app, config, state));
+    }
+
+    state.submitted.wait().await;
+    // Give the response to `/provision` time to actually reach the client.
+    Timer::after(Duration::from_millis(500)).await;
+
+    println!("Provisioning: credentials saved, rebooting");
+    esp_hal::reset::software_reset();
+}
+
+#[embassy_executor::task]
+async fn net_task(stack: &'static ApStack) {
+    stack.run().await
+}
+
+#[embassy_executor::task(pool_size = TASK_POOL_SIZE)]
+async fn web_task(
+    id: usize,
+    stack: &'static ApStack,
+    app: &'static App,
+    config: &'static picoserve::Config<Duration>,
+    state: &'static ProvisioningState,
+) -> ! {
+    let port = 80;
+    let mut tcp_rx_buffer = [0; 1024];
+    let mut tcp_tx_buffer = [0; 1024];
+    let mut http_buffer = [0; 2048];
+
+    picoserve::listen_and_serve_with_state(
+        id,
+        app,
+        config,
+        stack,
+        port,
+        &mut tcp_rx_buffer,
+        &mut tcp_tx_buffer,
+        &mut http_buffer,
+        &state,
+    )
+    .await
+}
+
+/// Owned JSON body, since the scan results don't live long enough to borrow
+/// into a `'static` response the way [`File`] does.
+struct JsonBody(heapless::Vec<u8, 1024>);
+
+impl picoserve::response::Content for JsonBody {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn content_length(&self) -> usize {
+        self.0.len()
+    }
+
+    async fn write_content<R: picoserve::io::Read, W: picoserve::io::Write>(
+        self,
+        _connection: picoserve::response::Connection<'_, R>,
+        mut writer: W,
+    ) -> Result<(), W::Error> {
+        writer.write_all(&self.0).await
+    }
+}
+
+impl picoserve::response::IntoResponse for JsonBody {
+    async fn write_to<
+        R: picoserve::io::Read,
+        W: picoserve::response::ResponseWriter<Error = R::Error>,
+    >(
+        self,
+        connection: picoserve::response::Connection<'_, R>,
+        response_writer: W,
+    ) -> Result<picoserve::ResponseSent, W::Error> {
+        response_writer
+            .write_response(connection, picoserve::response::Response::ok(self))
+            .await
+    }
+}
+
+async fn index() -> impl IntoResponse {
+    File::html(PAGE)
+}
+
+async fn scan(State(state): State<&'static ProvisioningState>) -> impl IntoResponse {
+    let mut buffer = [0u8; 1024];
+    let results = state.controller.lock().await.scan_n::<16>().await;
+
+    let len = match results {
+        Ok((networks, _count)) => {
+            let wire: heapless::Vec<ScannedNetwork, 16> = networks
+                .iter()
+                .map(|net| ScannedNetwork {
+                    ssid: net.ssid.as_str(),
+                    rssi: net.signal_strength,
+                    auth: match net.auth_method {
+                        Some(AuthMethod::None) | None => "open",
+                        Some(_) => "secured",
+                    },
+                })
+                .collect();
+            serde_json_core::to_slice(&wire, &mut buffer).unwrap()
+        }
+        Err(e) => {
+            println!("Provisioning: scan failed: {e:?}");
+            0
+        }
+    };
+
+    JsonBody(heapless::Vec::from_slice(&buffer[..len]).unwrap())
+}
+
+async fn provision(
+    State(state): State<&'static ProvisioningState>,
+    Json(form): Json<ProvisionForm>,
+) -> impl IntoResponse {
+    let mut saved = state.storage.load().await.unwrap_or_default();
+    saved.ssid = form.ssid;
+    saved.password = form.password;
+    state.storage.save(&saved).await;
+
+    state.submitted.signal(());
+    "ok"
+}
+
+fn make_app() -> picoserve::Router<AppRouter, &'static ProvisioningState> {
+    Router::new()
+        .route("/", get(index))
+        .route("/scan", get(scan))
+        .route("/provision", post(provision))
+}
+
+type AppRouter = impl picoserve::routing::PathRouter<&'static ProvisioningState>;
+type App = picoserve::Router<AppRouter, &'static ProvisioningState>;
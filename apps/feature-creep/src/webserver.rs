@@ -1,14 +1,14 @@
-use crate::wifi::Stack;
-use bhbadge2024::{lis2dh12::F32x3, ws2812b::Ws2812b};
+use crate::net::Stack;
+use bhbadge2024::{lis2dh12::F32x3, storage::Storage, ws2812b::Ws2812b};
 use embassy_executor::Spawner;
-use embassy_futures::select::Either;
+use embassy_futures::select::{select3, Either3};
 use embassy_sync::{
     blocking_mutex::raw::NoopRawMutex,
     pubsub::{PubSubChannel, Subscriber, WaitResult},
 };
 use embassy_time::{Duration, Timer};
 use esp_println::println;
-use feature_creep_types::{Command, Message};
+use feature_creep_types::{Command, Gesture, Message};
 use picoserve::{
     extract::State,
     io::embedded_io_async,
@@ -19,9 +19,17 @@ use picoserve::{
 
 pub const WEB_TASK_POOL_SIZE: usize = 3;
 
+// The accelerometer channel's permanent subscribers (BLE, the OLED display, and
+// the MQTT bridge), on top of up to `WEB_TASK_POOL_SIZE` concurrent websocket
+// connections. `PubSubChannel::subscriber()` returns `Err` once this cap is hit,
+// and every call site `.unwrap()`s it, so this must cover every subscriber.
+pub const ACCEL_CHANNEL_SUBS: usize = WEB_TASK_POOL_SIZE + 3;
+
 struct WebsocketHandler {
     ws2812b: Ws2812b,
-    subscriber: Subscriber<'static, NoopRawMutex, (F32x3, f32), 1, WEB_TASK_POOL_SIZE, 1>,
+    storage: Storage,
+    subscriber: Subscriber<'static, NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1>,
+    gesture_subscriber: Subscriber<'static, NoopRawMutex, Gesture, 1, WEB_TASK_POOL_SIZE, 1>,
 }
 
 impl ws::WebSocketCallback for WebsocketHandler {
@@ -34,24 +42,38 @@ impl ws::WebSocketCallback for WebsocketHandler {
         let mut buffer2 = [0; 1024];
 
         let close_reason = loop {
-            let msg = embassy_futures::select::select(
+            let msg = select3(
                 rx.next_message(&mut buffer),
                 self.subscriber.next_message(),
+                self.gesture_subscriber.next_message(),
             )
             .await;
             match msg {
-                Either::First(Ok(ws::Message::Text(_data))) => {
+                Either3::First(Ok(ws::Message::Text(_data))) => {
                     break Some((1003, "Only binary data accepted"))
                 }
-                Either::First(Ok(ws::Message::Binary(data))) => {
+                Either3::First(Ok(ws::Message::Binary(data))) => {
                     match serde_json_core::from_slice(data) {
                         Ok((Command::ChangeColor { index, rgb }, consumed))
                             if data.len() == consumed && index < 16 =>
                         {
+                            crate::animation::notify_manual_override();
                             self.ws2812b.set_pixel(index as usize, rgb);
                             // There was a race condition. We didn't understand it. Now it is no longer here. ¯\_(ツ)_/¯
                             Timer::after_micros(50).await;
                         }
+                        Ok((Command::SetAnimation(animation), consumed)) if data.len() == consumed => {
+                            crate::animation::select_animation(animation);
+                        }
+                        Ok((Command::SaveState, consumed)) if data.len() == consumed => {
+                            self.ws2812b.save_to_flash(&self.storage).await;
+                        }
+                        Ok((Command::SetFullScale(range), consumed)) if data.len() == consumed => {
+                            crate::accelerometer::set_range(range);
+                        }
+                        Ok((Command::SetBrightness(brightness), consumed)) if data.len() == consumed => {
+                            self.ws2812b.set_brightness(brightness);
+                        }
                         Ok((Command::QueryColors, _consumed)) => {
                             let mut res = [(0u8, 0u8, 0u8); 16];
                             self.ws2812b.with_frame_buffer(|f| {
@@ -83,15 +105,15 @@ impl ws::WebSocketCallback for WebsocketHandler {
                         }
                     }
                 }
-                Either::First(Ok(ws::Message::Close(reason))) => {
+                Either3::First(Ok(ws::Message::Close(reason))) => {
                     println!("Websocket close reason: {reason:?}");
                     break None;
                 }
-                Either::First(Ok(ws::Message::Ping(data))) => {
+                Either3::First(Ok(ws::Message::Ping(data))) => {
                     tx.send_pong(data).await?;
                 }
-                Either::First(Ok(ws::Message::Pong(_))) => (),
-                Either::First(Err(err)) => {
+                Either3::First(Ok(ws::Message::Pong(_))) => (),
+                Either3::First(Err(err)) => {
                     println!("Websocket Error: {err:?}");
 
                     let code = match err {
@@ -105,14 +127,21 @@ impl ws::WebSocketCallback for WebsocketHandler {
 
                     break Some((code, "Websocket Error"));
                 }
-                Either::Second(WaitResult::Lagged(_)) => (),
-                Either::Second(WaitResult::Message(m)) => {
+                Either3::Second(WaitResult::Lagged(_)) => (),
+                Either3::Second(WaitResult::Message(m)) => {
                     let len = serde_json_core::to_slice(
                         &Message::Accelerometer([m.0.x, m.0.y, m.0.z, m.1]),
                         &mut buffer2,
                     )
                     .unwrap();
 
+                    tx.send_binary(&buffer2[..len]).await.ok();
+                }
+                Either3::Third(WaitResult::Lagged(_)) => (),
+                Either3::Third(WaitResult::Message(gesture)) => {
+                    let len = serde_json_core::to_slice(&Message::Gesture(gesture), &mut buffer2)
+                        .unwrap();
+
                     tx.send_binary(&buffer2[..len]).await.ok();
                 }
             };
@@ -129,7 +158,9 @@ async fn websocket(
     upgrade
         .on_upgrade(WebsocketHandler {
             ws2812b: state.ws2812b,
+            storage: state.storage,
             subscriber: state.channel.subscriber().unwrap(),
+            gesture_subscriber: state.gesture_channel.subscriber().unwrap(),
         })
         .await
 }
@@ -170,7 +201,9 @@ fn make_app() -> picoserve::Router<AppRouter, &'static AppState> {
 
 pub struct AppState {
     pub ws2812b: Ws2812b,
-    pub channel: PubSubChannel<NoopRawMutex, (F32x3, f32), 1, WEB_TASK_POOL_SIZE, 1>,
+    pub storage: Storage,
+    pub channel: PubSubChannel<NoopRawMutex, (F32x3, f32), 1, ACCEL_CHANNEL_SUBS, 1>,
+    pub gesture_channel: PubSubChannel<NoopRawMutex, Gesture, 1, WEB_TASK_POOL_SIZE, 1>,
 }
 
 type AppRouter = impl picoserve::routing::PathRouter<&'static AppState>;
@@ -0,0 +1,8 @@
+#![no_std]
+#![feature(generic_const_exprs)]
+#![allow(incomplete_features)]
+
+pub mod lis2dh12;
+pub mod shared_i2c;
+pub mod storage;
+pub mod ws2812b;
@@ -0,0 +1,299 @@
+//! Minimal driver for the ST LIS2DH12 3-axis accelerometer, talking over
+//! [`SharedI2c`]. Datasheet: <https://www.st.com/resource/en/datasheet/lis2dh12.pdf>
+//!
+//! Only what the badge actually uses is implemented: output data rate, full-scale
+//! range, axis/temperature enable, polling the accelerometer and temperature
+//! outputs, and the click/free-fall interrupt engine used by [`Gesture`] detection.
+
+use crate::shared_i2c::SharedI2c;
+
+const WHO_AM_I: u8 = 0x0F;
+const WHO_AM_I_VALUE: u8 = 0x33;
+
+const CTRL_REG1: u8 = 0x20;
+const CTRL_REG3: u8 = 0x22;
+const CTRL_REG4: u8 = 0x23;
+const CTRL_REG5: u8 = 0x24;
+const TEMP_CFG_REG: u8 = 0x1F;
+const OUT_X_L: u8 = 0x28;
+const OUT_TEMP_L: u8 = 0x0C;
+
+const CLICK_CFG: u8 = 0x38;
+const CLICK_SRC: u8 = 0x39;
+const CLICK_THS: u8 = 0x3A;
+const TIME_LIMIT: u8 = 0x3B;
+const TIME_LATENCY: u8 = 0x3C;
+const TIME_WINDOW: u8 = 0x3D;
+
+const INT1_CFG: u8 = 0x30;
+const INT1_SRC: u8 = 0x31;
+const INT1_THS: u8 = 0x32;
+const INT1_DURATION: u8 = 0x33;
+
+/// Auto-increment bit OR'd onto a register address for a multi-byte read/write.
+const AUTO_INCREMENT: u8 = 0x80;
+
+/// A 3-axis vector of accelerometer readings, in g.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct F32x3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// The I2C address is set by the state of the `SDO`/`SA0` pin.
+#[derive(Debug, Clone, Copy)]
+pub enum SlaveAddr {
+    /// `SDO` tied low: `0x18`.
+    Default,
+    /// `SDO` tied high: `0x19`.
+    Alternative(bool),
+}
+
+impl SlaveAddr {
+    fn address(self) -> u8 {
+        match self {
+            SlaveAddr::Default => 0x18,
+            SlaveAddr::Alternative(true) => 0x19,
+            SlaveAddr::Alternative(false) => 0x18,
+        }
+    }
+}
+
+/// Output data rate, written into the top nibble of `CTRL_REG1`.
+#[derive(Debug, Clone, Copy)]
+pub enum Odr {
+    PowerDown,
+    Hz1,
+    Hz10,
+    Hz25,
+    Hz50,
+    Hz100,
+    Hz200,
+    Hz400,
+}
+
+impl Odr {
+    fn bits(self) -> u8 {
+        match self {
+            Odr::PowerDown => 0b0000,
+            Odr::Hz1 => 0b0001,
+            Odr::Hz10 => 0b0010,
+            Odr::Hz25 => 0b0011,
+            Odr::Hz50 => 0b0100,
+            Odr::Hz100 => 0b0101,
+            Odr::Hz200 => 0b0110,
+            Odr::Hz400 => 0b0111,
+        }
+    }
+}
+
+/// Power mode, trading resolution for current draw.
+#[derive(Debug, Clone, Copy)]
+pub enum Mode {
+    LowPower,
+    Normal,
+    HighResolution,
+}
+
+/// Accelerometer full-scale range, runtime-selectable via
+/// `Command::SetFullScale`. The discriminants are the `FS` bits written into
+/// `CTRL_REG4`. This is a distinct type from `feature_creep_types::FullScale`
+/// (the wire type, serialized by variant name, not by discriminant) -
+/// `accelerometer::set_range` hand-matches one onto the other.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[repr(u8)]
+pub enum FullScale {
+    G2 = 0b00,
+    G4 = 0b01,
+    G8 = 0b10,
+    G16 = 0b11,
+}
+
+impl FullScale {
+    fn bits(self) -> u8 {
+        self as u8
+    }
+
+    /// mg per LSB in high-resolution (12-bit) mode.
+    fn sensitivity_mg_per_lsb(self) -> f32 {
+        match self {
+            FullScale::G2 => 1.0,
+            FullScale::G4 => 2.0,
+            FullScale::G8 => 4.0,
+            FullScale::G16 => 12.0,
+        }
+    }
+}
+
+/// A gesture recognized by the click/interrupt engine, published alongside the raw
+/// accelerometer samples.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Gesture {
+    SingleTap,
+    DoubleTap,
+    FreeFall,
+}
+
+pub struct Lis2dh12 {
+    i2c: SharedI2c,
+    address: u8,
+    fs: FullScale,
+}
+
+impl Lis2dh12 {
+    /// Opens the device and checks `WHO_AM_I`.
+    pub async fn new(i2c: SharedI2c, addr: SlaveAddr) -> Result<Self, esp_hal::i2c::Error> {
+        let address = addr.address();
+        let mut who_am_i = [0u8; 1];
+        i2c.write_read(address, &[WHO_AM_I], &mut who_am_i).await?;
+        debug_assert_eq!(who_am_i[0], WHO_AM_I_VALUE, "unexpected LIS2DH12 WHO_AM_I");
+
+        Ok(Self {
+            i2c,
+            address,
+            fs: FullScale::G2,
+        })
+    }
+
+    /// Reboots the device's memory content and clears `CTRL_REG1`, `CTRL_REG3`,
+    /// `CTRL_REG4` and `CTRL_REG5`. `CTRL_REG2` and `CTRL_REG6` are not written.
+    pub async fn reset(&mut self) -> Result<(), esp_hal::i2c::Error> {
+        self.write_register(CTRL_REG5, 0b1000_0000).await?;
+        embassy_time::Timer::after_millis(10).await;
+        for register in [CTRL_REG1, CTRL_REG3, CTRL_REG4, CTRL_REG5] {
+            self.write_register(register, 0).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn set_odr(&mut self, odr: Odr) -> Result<(), esp_hal::i2c::Error> {
+        let mut ctrl_reg1 = self.read_register(CTRL_REG1).await?;
+        ctrl_reg1 = (ctrl_reg1 & 0x0F) | (odr.bits() << 4);
+        self.write_register(CTRL_REG1, ctrl_reg1).await
+    }
+
+    pub async fn set_mode(&mut self, mode: Mode) -> Result<(), esp_hal::i2c::Error> {
+        let mut ctrl_reg1 = self.read_register(CTRL_REG1).await?;
+        let mut ctrl_reg4 = self.read_register(CTRL_REG4).await?;
+        match mode {
+            Mode::LowPower => ctrl_reg1 |= 0b0000_1000,
+            Mode::Normal => {
+                ctrl_reg1 &= !0b0000_1000;
+                ctrl_reg4 &= !0b0000_1000;
+            }
+            Mode::HighResolution => {
+                ctrl_reg1 &= !0b0000_1000;
+                ctrl_reg4 |= 0b0000_1000;
+            }
+        }
+        self.write_register(CTRL_REG1, ctrl_reg1).await?;
+        self.write_register(CTRL_REG4, ctrl_reg4).await
+    }
+
+    pub async fn set_fs(&mut self, fs: FullScale) -> Result<(), esp_hal::i2c::Error> {
+        let mut ctrl_reg4 = self.read_register(CTRL_REG4).await?;
+        ctrl_reg4 = (ctrl_reg4 & !0b0011_0000) | (fs.bits() << 4);
+        self.write_register(CTRL_REG4, ctrl_reg4).await?;
+        self.fs = fs;
+        Ok(())
+    }
+
+    pub async fn enable_axis(&mut self, (x, y, z): (bool, bool, bool)) -> Result<(), esp_hal::i2c::Error> {
+        let mut ctrl_reg1 = self.read_register(CTRL_REG1).await?;
+        ctrl_reg1 = (ctrl_reg1 & !0b0000_0111)
+            | (x as u8)
+            | ((y as u8) << 1)
+            | ((z as u8) << 2);
+        self.write_register(CTRL_REG1, ctrl_reg1).await
+    }
+
+    pub async fn enable_temp(&mut self, enabled: bool) -> Result<(), esp_hal::i2c::Error> {
+        self.write_register(TEMP_CFG_REG, if enabled { 0b1100_0000 } else { 0 })
+            .await
+    }
+
+    /// Reads the X/Y/Z registers and converts them to g using the configured full scale.
+    pub async fn accel_norm(&mut self) -> Result<F32x3, esp_hal::i2c::Error> {
+        let mut raw = [0u8; 6];
+        self.i2c
+            .write_read(self.address, &[OUT_X_L | AUTO_INCREMENT], &mut raw)
+            .await?;
+
+        let sensitivity = self.fs.sensitivity_mg_per_lsb() / 1000.0;
+        // The output registers hold a left-justified value; shift it down to 12 bits so the
+        // high-resolution sensitivity applies (in normal/low-power mode the low bits are 0).
+        let to_g = |lo: u8, hi: u8| ((i16::from_le_bytes([lo, hi]) >> 4) as f32) * sensitivity;
+
+        Ok(F32x3 {
+            x: to_g(raw[0], raw[1]),
+            y: to_g(raw[2], raw[3]),
+            z: to_g(raw[4], raw[5]),
+        })
+    }
+
+    /// Reads the auxiliary temperature channel. The datasheet only guarantees a
+    /// relative reading (1 degree/LSB around an unspecified reference), so callers
+    /// are expected to apply their own offset, as `accelerometer.rs` does.
+    pub async fn get_temp_outf(&mut self) -> Result<f32, esp_hal::i2c::Error> {
+        let mut raw = [0u8; 2];
+        self.i2c
+            .write_read(self.address, &[OUT_TEMP_L | AUTO_INCREMENT], &mut raw)
+            .await?;
+        Ok((i16::from_le_bytes(raw) >> 6) as f32 / 4.0)
+    }
+
+    /// Programs the click engine for single/double tap and the INT1 engine for
+    /// free-fall, routing both to the `INT1` pin.
+    pub async fn configure_gestures(&mut self) -> Result<(), esp_hal::i2c::Error> {
+        // Route click and IA1 (INT1 source) interrupts to the INT1 pin.
+        self.write_register(CTRL_REG3, 0b1100_0000).await?;
+
+        // CLICK_CFG: enable single- and double-click detection on all three axes
+        // (XS, XD, YS, YD, ZS, ZD - bits 0 through 5).
+        self.write_register(CLICK_CFG, 0b0011_1111).await?;
+        self.write_register(CLICK_THS, 0x2A).await?;
+        self.write_register(TIME_LIMIT, 0x0A).await?;
+        self.write_register(TIME_LATENCY, 0x14).await?;
+        self.write_register(TIME_WINDOW, 0x3C).await?;
+
+        // INT1_CFG: free-fall is "all axes below threshold" (AOI = 1 for the AND
+        // combination, ZLIE/YLIE/XLIE set).
+        self.write_register(INT1_CFG, 0b1001_0101).await?;
+        self.write_register(INT1_THS, 0x10).await?;
+        self.write_register(INT1_DURATION, 0x02).await?;
+
+        Ok(())
+    }
+
+    /// Polls `CLICK_SRC`/`INT1_SRC` and returns the gesture that fired, if any. Meant
+    /// to be called whenever the `INT1` GPIO goes high, or periodically if it isn't
+    /// wired up.
+    pub async fn poll_gesture(&mut self) -> Result<Option<Gesture>, esp_hal::i2c::Error> {
+        let click_src = self.read_register(CLICK_SRC).await?;
+        // CLICK_SRC bit 4 is SClick, bit 5 is DClick (bits 0-2 are the X/Y/Z axis flags).
+        if click_src & 0b0001_0000 != 0 {
+            return Ok(Some(Gesture::SingleTap));
+        }
+        if click_src & 0b0010_0000 != 0 {
+            return Ok(Some(Gesture::DoubleTap));
+        }
+
+        let int1_src = self.read_register(INT1_SRC).await?;
+        if int1_src & 0b0100_0000 != 0 {
+            return Ok(Some(Gesture::FreeFall));
+        }
+
+        Ok(None)
+    }
+
+    async fn read_register(&mut self, register: u8) -> Result<u8, esp_hal::i2c::Error> {
+        let mut value = [0u8; 1];
+        self.i2c.write_read(self.address, &[register], &mut value).await?;
+        Ok(value[0])
+    }
+
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), esp_hal::i2c::Error> {
+        self.i2c.write(self.address, &[register, value]).await
+    }
+}
@@ -0,0 +1,257 @@
+//! Wear-levelled persistence of badge state (LED frame + Wi-Fi credentials) into a
+//! couple of dedicated flash sectors, so the badge survives a power cycle without
+//! needing to re-flash or re-pair it.
+//!
+//! The layout is a simple append-only journal split across two 4 KiB sectors:
+//! each record is `[magic: u32][seq: u32][payload][crc32: u32]`, padded with
+//! trailing zero bytes up to the next 4-byte boundary (flash writes must be
+//! word-aligned), written sequentially into the active sector. On boot we
+//! scan both sectors and keep the record with the highest `seq` whose crc
+//! checks out. When the active
+//! sector no longer has room for another record, the other sector is erased,
+//! the new record is written to its start, and that sector becomes active; the
+//! old sector keeps its records until the next roll-over erases it. A torn write (power loss mid-write) just leaves a
+//! record with a bad crc, which is skipped during the scan.
+
+use core::cell::RefCell;
+
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use esp_storage::FlashStorage;
+use static_cell::StaticCell;
+
+use crate::ws2812b::PIXEL_COUNT;
+
+const SECTOR_SIZE: u32 = 4096;
+// Two sectors near the top of the external flash, below the app image, reserved
+// for this journal. Adjust these if the partition table changes.
+const SECTOR_OFFSETS: [u32; 2] = [0x1f_0000, 0x1f_0000 + SECTOR_SIZE];
+
+const MAGIC: u32 = 0xBADB_0501;
+
+const SSID_LEN: usize = 32;
+const PASSWORD_LEN: usize = 64;
+// [magic][seq][frame][brightness][ssid_len][ssid][password_len][password][crc]
+const PAYLOAD_LEN: usize = PIXEL_COUNT * 3 + 1 + 1 + SSID_LEN + 1 + PASSWORD_LEN;
+const UNPADDED_RECORD_LEN: usize = 4 + 4 + PAYLOAD_LEN + 4;
+// `esp-storage`'s `NorFlash::WRITE_SIZE` is 4, and `write` rejects any length or offset
+// that isn't a multiple of it, so every record is padded up to the next word boundary with
+// trailing zero bytes that are simply ignored when reading it back.
+const RECORD_LEN: usize = (UNPADDED_RECORD_LEN + 3) / 4 * 4;
+
+/// Everything we persist across a power cycle.
+#[derive(Clone)]
+pub struct PersistedState {
+    pub frame: [[u8; 3]; PIXEL_COUNT],
+    pub brightness: u8,
+    pub ssid: heapless::String<SSID_LEN>,
+    pub password: heapless::String<PASSWORD_LEN>,
+}
+
+impl Default for PersistedState {
+    fn default() -> Self {
+        Self {
+            frame: [[0; 3]; PIXEL_COUNT],
+            brightness: 255,
+            ssid: heapless::String::new(),
+            password: heapless::String::new(),
+        }
+    }
+}
+
+impl PersistedState {
+    fn write_payload(&self, out: &mut [u8; PAYLOAD_LEN]) {
+        let mut offset = 0;
+        for pixel in &self.frame {
+            out[offset..offset + 3].copy_from_slice(pixel);
+            offset += 3;
+        }
+        out[offset] = self.brightness;
+        offset += 1;
+
+        out[offset] = self.ssid.len() as u8;
+        offset += 1;
+        out[offset..offset + self.ssid.len()].copy_from_slice(self.ssid.as_bytes());
+        offset += SSID_LEN;
+
+        out[offset] = self.password.len() as u8;
+        offset += 1;
+        out[offset..offset + self.password.len()].copy_from_slice(self.password.as_bytes());
+    }
+
+    fn read_payload(payload: &[u8; PAYLOAD_LEN]) -> Option<Self> {
+        let mut offset = 0;
+        let mut frame = [[0u8; 3]; PIXEL_COUNT];
+        for pixel in &mut frame {
+            pixel.copy_from_slice(&payload[offset..offset + 3]);
+            offset += 3;
+        }
+        let brightness = payload[offset];
+        offset += 1;
+
+        let ssid_len = payload[offset] as usize;
+        offset += 1;
+        let ssid =
+            heapless::String::from_utf8(heapless::Vec::from_slice(&payload[offset..offset + ssid_len]).ok()?)
+                .ok()?;
+        offset += SSID_LEN;
+
+        let password_len = payload[offset] as usize;
+        offset += 1;
+        let password = heapless::String::from_utf8(
+            heapless::Vec::from_slice(&payload[offset..offset + password_len]).ok()?,
+        )
+        .ok()?;
+
+        Some(Self {
+            frame,
+            brightness,
+            ssid,
+            password,
+        })
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+struct Inner {
+    flash: FlashStorage,
+    sector: usize,
+    write_offset: u32,
+    seq: u32,
+}
+
+impl Inner {
+    fn append(&mut self, state: &PersistedState) {
+        if self.write_offset + RECORD_LEN as u32 > SECTOR_SIZE {
+            self.roll_over(state);
+            return;
+        }
+
+        self.seq = self.seq.wrapping_add(1);
+        let mut record = [0u8; RECORD_LEN];
+        record[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        record[4..8].copy_from_slice(&self.seq.to_le_bytes());
+
+        let mut payload = [0u8; PAYLOAD_LEN];
+        state.write_payload(&mut payload);
+        record[8..8 + PAYLOAD_LEN].copy_from_slice(&payload);
+
+        let crc = crc32(&record[0..8 + PAYLOAD_LEN]);
+        record[8 + PAYLOAD_LEN..8 + PAYLOAD_LEN + 4].copy_from_slice(&crc.to_le_bytes());
+
+        let address = SECTOR_OFFSETS[self.sector] + self.write_offset;
+        self.flash.write(address, &record).unwrap();
+        self.write_offset += RECORD_LEN as u32;
+    }
+
+    fn roll_over(&mut self, state: &PersistedState) {
+        let next_sector = 1 - self.sector;
+        self.flash
+            .erase(
+                SECTOR_OFFSETS[next_sector],
+                SECTOR_OFFSETS[next_sector] + SECTOR_SIZE,
+            )
+            .unwrap();
+        self.sector = next_sector;
+        self.write_offset = 0;
+        self.append(state);
+    }
+
+    fn scan(&mut self) -> Option<PersistedState> {
+        let mut best: Option<(u32, PersistedState)> = None;
+        for (sector, &base) in SECTOR_OFFSETS.iter().enumerate() {
+            let mut offset = 0;
+            while offset + RECORD_LEN as u32 <= SECTOR_SIZE {
+                let mut record = [0u8; RECORD_LEN];
+                self.flash.read(base + offset, &mut record).unwrap();
+
+                let magic = u32::from_le_bytes(record[0..4].try_into().unwrap());
+                if magic != MAGIC {
+                    break;
+                }
+                let seq = u32::from_le_bytes(record[4..8].try_into().unwrap());
+                let crc =
+                    u32::from_le_bytes(record[8 + PAYLOAD_LEN..8 + PAYLOAD_LEN + 4].try_into().unwrap());
+                if crc32(&record[0..8 + PAYLOAD_LEN]) != crc {
+                    offset += RECORD_LEN as u32;
+                    continue;
+                }
+
+                let payload: &[u8; PAYLOAD_LEN] = record[8..8 + PAYLOAD_LEN].try_into().unwrap();
+                if let Some(state) = PersistedState::read_payload(payload) {
+                    let newer = best.as_ref().map(|(best_seq, _)| seq > *best_seq).unwrap_or(true);
+                    if newer {
+                        self.sector = sector;
+                        self.write_offset = offset + RECORD_LEN as u32;
+                        self.seq = seq;
+                        best = Some((seq, state));
+                    }
+                }
+                offset += RECORD_LEN as u32;
+            }
+        }
+        best.map(|(_, state)| state)
+    }
+}
+
+/// Handle to the persisted-state journal, cheap to copy and share between tasks,
+/// mirroring [`crate::shared_i2c::SharedI2c`].
+#[derive(Copy, Clone)]
+pub struct Storage {
+    ptr: &'static Mutex<NoopRawMutex, RefCell<Inner>>,
+}
+
+impl Storage {
+    /// Opens the journal, scanning both sectors for the most recent valid record.
+    pub fn new(flash: FlashStorage) -> Self {
+        static MEMORY: StaticCell<Mutex<NoopRawMutex, RefCell<Inner>>> = StaticCell::new();
+
+        let mut inner = Inner {
+            flash,
+            sector: 0,
+            write_offset: 0,
+            seq: 0,
+        };
+        if inner.scan().is_none() {
+            // Factory-fresh badge: neither sector has ever been erased, so sector 0
+            // can't be assumed to read back as 0xFF. Erase it before the first
+            // append() writes into it at write_offset 0.
+            inner
+                .flash
+                .erase(SECTOR_OFFSETS[0], SECTOR_OFFSETS[0] + SECTOR_SIZE)
+                .unwrap();
+            inner.sector = 0;
+            inner.write_offset = 0;
+        }
+
+        Self {
+            ptr: MEMORY.init(Mutex::new(RefCell::new(inner))),
+        }
+    }
+
+    /// Returns the last state that was saved, or `None` if the journal is empty
+    /// (e.g. on a factory-fresh badge).
+    pub async fn load(&self) -> Option<PersistedState> {
+        self.ptr.lock().await.borrow_mut().scan()
+    }
+
+    /// Appends a new record with the given state, rolling over to the other
+    /// sector first if the active one is full.
+    pub async fn save(&self, state: &PersistedState) {
+        self.ptr.lock().await.borrow_mut().append(state);
+    }
+}
@@ -1,4 +1,7 @@
-use core::cell::RefCell;
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicU8, Ordering},
+};
 
 use embassy_executor::SendSpawner;
 use embassy_sync::{
@@ -17,31 +20,42 @@ use esp_hal::{
 use micromath::F32Ext;
 use static_cell::ConstStaticCell;
 
-const PIXEL_COUNT: usize = 16;
+/// Pixel count used by callers that don't need a different strip length.
+pub(crate) const PIXEL_COUNT: usize = 16;
+
+/// Pulse codes per byte (one per bit). Each pixel's pulse codes are followed by their own
+/// trailing end marker (see `PulseCodeArray`).
+const CODES_PER_BYTE: usize = 8;
+const BYTES_PER_PIXEL: usize = 3;
 
-struct CommunicationState {
-    frame_buffer: BufferMutex,
+struct CommunicationState<const N: usize> {
+    frame_buffer: BufferMutex<N>,
     activation_signal: ActivationSignal,
+    brightness: AtomicU8,
 }
 
-type BufferMutex = Mutex<CriticalSectionRawMutex, RefCell<[[u8; 3]; PIXEL_COUNT]>>;
+type BufferMutex<const N: usize> = Mutex<CriticalSectionRawMutex, RefCell<[[u8; 3]; N]>>;
 type ActivationSignal = Signal<CriticalSectionRawMutex, ()>;
-// Ideally we would write all of the pulsecodes at the same time
-// but the RMT only has space for up to 48 pulses, so we split up
-// the pulse codes by pixel. We store 25 pulsecodes, so we have
-// one extra for the end code.
-type PulseCodeArray = [[u32; 25]; PIXEL_COUNT];
+// One pulse-code array per pixel, each with a trailing end code. The RMT only has room for
+// 48 pulses at a time, and `TxChannelAsync::transmit` in this esp-hal version has no way to
+// refill that RAM from a larger buffer mid-transmit (wrapping mode doesn't work with the
+// async interface) - a threshold/interrupt-driven refill would need a lower-level API than
+// `esp-hal` currently exposes here. So each pixel is still sent with its own
+// `transmit().await` call below, leaving a small (out-of-spec but in-practice-fine) gap
+// between pixels instead of the single gap-free burst the strip really wants.
+type PulseCodeArray<const N: usize> = [[u32; BYTES_PER_PIXEL * CODES_PER_BYTE + 1]; N];
 
+/// WS2812B driver over RMT, generic over the number of pixels on the strip.
 #[derive(Copy, Clone)]
-pub struct Ws2812b {
-    state: &'static CommunicationState,
+pub struct Ws2812b<const N: usize = PIXEL_COUNT> {
+    state: &'static CommunicationState<N>,
 }
 
-pub struct FrameBuffer<'a> {
-    frame_buffer: &'a mut [[u8; 3]; PIXEL_COUNT],
+pub struct FrameBuffer<'a, const N: usize> {
+    frame_buffer: &'a mut [[u8; 3]; N],
 }
 
-impl<'a> FrameBuffer<'a> {
+impl<'a, const N: usize> FrameBuffer<'a, N> {
     /// Sets a single pixel.
     ///
     /// ### Example
@@ -58,16 +72,55 @@ impl<'a> FrameBuffer<'a> {
     }
 
     /// Gets raw access to the frame_buffer. Note that the pixels are stored in grb format.
-    pub fn raw_mut(&mut self) -> &mut [[u8; 3]; PIXEL_COUNT] {
-        &mut self.frame_buffer
+    pub fn raw_mut(&mut self) -> &mut [[u8; 3]; N] {
+        self.frame_buffer
+    }
+
+    /// Sets a single pixel from HSV. `hue` is in degrees `[0, 360)`, `saturation` and
+    /// `value` are in `[0.0, 1.0]`.
+    pub fn set_pixel_hsv(&mut self, index: usize, hue: f32, saturation: f32, value: f32) {
+        self.set_pixel(index, hsv_to_rgb(hue, saturation, value));
     }
 }
 
+/// Converts HSV (hue in degrees, saturation/value in `[0.0, 1.0]`) to an RGB triple.
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> (u8, u8, u8) {
+    let hp = (hue.rem_euclid(360.0)) / 60.0;
+    let c = saturation * value;
+    let x = c * (1.0 - (hp % 2.0 - 1.0).abs());
+    let m = value - c;
+
+    let (r, g, b) = if hp < 1.0 {
+        (c, x, 0.0)
+    } else if hp < 2.0 {
+        (x, c, 0.0)
+    } else if hp < 3.0 {
+        (0.0, c, x)
+    } else if hp < 4.0 {
+        (0.0, x, c)
+    } else if hp < 5.0 {
+        (x, 0.0, c)
+    } else {
+        (c, 0.0, x)
+    };
+
+    (
+        ((r + m) * 255.0) as u8,
+        ((g + m) * 255.0) as u8,
+        ((b + m) * 255.0) as u8,
+    )
+}
+
 const CHANNEL: u8 = 0;
 
-impl Ws2812b {
+impl Ws2812b<PIXEL_COUNT> {
     /// Initializes the ws2812b driver.
     ///
+    /// `#[embassy_executor::task]` functions must be non-generic, so unlike the rest of
+    /// this type, `new` is only implemented for the default, badge-sized strip - the same
+    /// way `ethernet::mac_task` is pinned to one concrete `W5500` instantiation instead of
+    /// being spawned generically.
+    ///
     /// ## Example
     ///
     /// ```
@@ -83,13 +136,15 @@ impl Ws2812b {
     where
         P: OutputPin,
     {
-        static STATE: ConstStaticCell<CommunicationState> =
+        static STATE: ConstStaticCell<CommunicationState<PIXEL_COUNT>> =
             ConstStaticCell::new(CommunicationState {
                 frame_buffer: Mutex::new(RefCell::new([[0; 3]; PIXEL_COUNT])),
                 activation_signal: Signal::new(),
+                brightness: AtomicU8::new(255),
             });
-        static PULSECODES: ConstStaticCell<PulseCodeArray> =
-            ConstStaticCell::new([[0u32; 25]; PIXEL_COUNT]);
+        static PULSECODES: ConstStaticCell<PulseCodeArray<PIXEL_COUNT>> = ConstStaticCell::new(
+            [[0u32; BYTES_PER_PIXEL * CODES_PER_BYTE + 1]; PIXEL_COUNT],
+        );
 
         let state = STATE.take();
         let pulsecodes = PULSECODES.take();
@@ -113,13 +168,16 @@ impl Ws2812b {
                 channel,
                 &state.frame_buffer,
                 &state.activation_signal,
+                &state.brightness,
                 pulsecodes,
             ))
             .unwrap();
         state.activation_signal.signal(());
         Self { state }
     }
+}
 
+impl<const N: usize> Ws2812b<N> {
     /// Gets access to the frame buffer.
     ///
     /// ### Example
@@ -131,7 +189,7 @@ impl Ws2812b {
     /// ```
     pub fn with_frame_buffer<F, R>(&self, f: F) -> R
     where
-        F: for<'a> FnOnce(&'a mut FrameBuffer<'a>) -> R,
+        F: for<'a> FnOnce(&'a mut FrameBuffer<'a, N>) -> R,
     {
         let result = self.state.frame_buffer.lock(|frame_buffer| {
             let mut frame_buffer = frame_buffer.borrow_mut();
@@ -157,46 +215,89 @@ impl Ws2812b {
             frame_buffer.set_pixel(index, rgb);
         });
     }
+
+    /// Sets the global brightness (0-255) applied to every pixel when the frame is sent
+    /// to the strip. Takes effect the next time the frame buffer is flushed.
+    pub fn set_brightness(&self, brightness: u8) {
+        self.state.brightness.store(brightness, Ordering::Relaxed);
+        self.state.activation_signal.signal(());
+    }
+
+    /// Returns the current global brightness.
+    pub fn brightness(&self) -> u8 {
+        self.state.brightness.load(Ordering::Relaxed)
+    }
+
+    /// Saves the current frame buffer to flash via `storage`, so it survives a reset.
+    pub async fn save_to_flash(&self, storage: &crate::storage::Storage) {
+        let mut state = storage.load().await.unwrap_or_default();
+        self.with_frame_buffer(|frame_buffer| {
+            state.frame = *frame_buffer.raw_mut();
+        });
+        storage.save(&state).await;
+    }
+
+    /// Restores a previously saved frame buffer from flash, if one exists. Meant to be
+    /// called once at boot, before the first pixel is ever set, so the badge lights up
+    /// with whatever it was showing before the last reset instead of all-off.
+    pub async fn restore_from_flash(&self, storage: &crate::storage::Storage) {
+        if let Some(state) = storage.load().await {
+            self.with_frame_buffer(|frame_buffer| {
+                *frame_buffer.raw_mut() = state.frame;
+            });
+        }
+    }
 }
 
 type Channel = esp_hal::rmt::Channel<Async, CHANNEL>;
 
-#[embassy_executor::task]
+/// Gamma-correction lookup table: `gamma_lut()[in] == round(255 * (in/255)^2.2)`.
+/// Perceived brightness is not linear in the raw 0-255 channel value, so without this
+/// a "half" brightness command looks much brighter than half to the eye.
+fn gamma_lut() -> [u8; 256] {
+    let mut lut = [0u8; 256];
+    for (i, out) in lut.iter_mut().enumerate() {
+        let normalized = i as f32 / 255.0;
+        *out = (255.0 * normalized.powf(2.2)).round() as u8;
+    }
+    lut
+}
+
+// Pinned to `PIXEL_COUNT`, not generic over `N`: `#[embassy_executor::task]` functions
+// must be non-generic (see the `W5500Dyn` comment in `ethernet.rs` for the same
+// constraint worked around the same way), and `Ws2812b::new` is the only caller.
+#[embassy_executor::task(pool_size = 1)]
 async fn handler(
     mut channel: Channel,
-    frame_buffer: &'static BufferMutex,
+    frame_buffer: &'static BufferMutex<PIXEL_COUNT>,
     activation_signal: &'static ActivationSignal,
-    pulsecodes: &'static mut PulseCodeArray,
+    brightness: &'static AtomicU8,
+    pulsecodes: &'static mut PulseCodeArray<PIXEL_COUNT>,
 ) {
+    let gamma = gamma_lut();
+
     loop {
         activation_signal.wait().await;
 
-        const CORRECTIONS: [f32; 3] = [
-            0.3 * 177.0 / 256.0,
-            0.3 * 256.0 / 256.0,
-            0.3 * 241.0 / 256.0,
-        ];
+        let brightness = brightness.load(Ordering::Relaxed);
 
         frame_buffer.lock(|frame_buffer| {
             let frame_buffer = frame_buffer.borrow();
-            for (chunk, pulsecodes) in frame_buffer.iter().zip(pulsecodes.iter_mut()) {
-                for ((b, pulsecodes), correction) in chunk
-                    .iter()
-                    .zip(pulsecodes.chunks_exact_mut(8))
-                    .zip(CORRECTIONS)
-                {
-                    write_pulse_codes(*b, pulsecodes.try_into().unwrap(), correction);
+            for (pixel, codes) in frame_buffer.iter().zip(pulsecodes.iter_mut()) {
+                for (b, out) in pixel.iter().zip(codes.chunks_exact_mut(CODES_PER_BYTE)) {
+                    let scaled = (*b as u16 * brightness as u16 / 255) as u8;
+                    let corrected = gamma[scaled as usize];
+                    write_pulse_codes(corrected, out.try_into().unwrap());
                 }
             }
         });
 
-        // Send the pulsecodes to the rmt one at a time. Ideally we would write all of them at
-        // once, but it does not have enough ram. We could in principle use wrapping mode, but that
-        // does not work with the async interface.
-        //
-        // In practice this is should be fine: We will get slightly longer pauses between pulses
-        // especially if another task does not yield in time, however slightly longer pauses should
-        // be fine, even if it is somewhat outside the spec.
+        // Send the pulse codes one pixel at a time: the RMT only has room for 48 pulses,
+        // not enough for the whole frame at once (see the `PulseCodeArray` comment). This
+        // leaves a small gap between pixels, technically out of spec, but it works fine in
+        // practice. NOTE: a single gap-free burst (refilling RMT RAM from a
+        // threshold/interrupt callback as the strip drains it) is not implemented - this
+        // still sends per-pixel, so that part of the original request is not done.
         for pulsecode in pulsecodes.iter() {
             channel.transmit(pulsecode).await.unwrap();
         }
@@ -208,7 +309,7 @@ async fn handler(
 }
 
 #[inline(always)]
-fn write_pulse_codes(byte: u8, out: &mut [u32; 8], correction: f32) {
+fn write_pulse_codes(byte: u8, out: &mut [u32; 8]) {
     // These numbers do *not* match the datasheet, they match https://github.com/karlri/esp32-rmt-ws2812b/blob/main/src/lib.rs
     // We make the zero pulses shorter and the one pulses longer. This seems to work okay
     const ZERO: PulseCode = PulseCode {
@@ -238,7 +339,6 @@ fn write_pulse_codes(byte: u8, out: &mut [u32; 8], correction: f32) {
     // Write out the bits one at a time, starting with the most significant bit.
     // This code is a bit weird-looking, because Tethys decided to do a silly
     // micro-optimization instead of writing the most readable version
-    let byte = ((byte as f32) * correction).round() as u8;
     let mut byte = (byte as u32) << 24;
     for out in out {
         *out = if (byte & 0x80000000) == 0 {
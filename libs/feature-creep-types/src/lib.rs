@@ -21,6 +21,38 @@ pub enum Command {
         rgb: (u8, u8, u8),
     },
     QueryColors,
+    #[serde(rename = "s")]
+    SetAnimation(#[serde(rename = "a")] Animation),
+    #[serde(rename = "w")]
+    SaveState,
+    #[serde(rename = "f")]
+    SetFullScale(FullScale),
+    #[serde(rename = "b")]
+    SetBrightness(u8),
+}
+
+/// Accelerometer full-scale range, as selected over the websocket/MQTT/BLE
+/// control path. Serialized by variant name (not by discriminant), and
+/// hand-matched onto the distinct [`bhbadge2024::lis2dh12::FullScale`] by
+/// `accelerometer::set_range`.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[repr(u8)]
+pub enum FullScale {
+    G2 = 0b00,
+    G4 = 0b01,
+    G8 = 0b10,
+    G16 = 0b11,
+}
+
+/// Built-in on-device animations, played by the `animate` task until a new
+/// `SetAnimation` replaces them. A `ChangeColor` command pauses the animation for a
+/// few seconds, after which it resumes.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Animation {
+    Solid { rgb: (u8, u8, u8) },
+    RainbowCycle,
+    Breathing { rgb: (u8, u8, u8) },
+    Comet { rgb: (u8, u8, u8) },
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -29,4 +61,15 @@ pub enum Message {
     CurrentColors([(u8, u8, u8); 16]),
     #[serde(rename = "a")]
     Accelerometer([f32; 4]),
+    #[serde(rename = "g")]
+    Gesture(Gesture),
+}
+
+/// Wire representation of a [`bhbadge2024::lis2dh12::Gesture`] recognized by the
+/// accelerometer's click/interrupt engine.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Gesture {
+    SingleTap,
+    DoubleTap,
+    FreeFall,
 }